@@ -0,0 +1,99 @@
+//! Packed `VoxelChunk` storage against the raw `Vec<u8>` the generators produce.
+//!
+//! Run with `cargo bench -p engine --bench chunk_storage`.
+
+use engine::{
+    chunk_gen::{CaveGenerator, FlatGenerator, HeightmapGenerator, TerrainGenerator, CHUNK_SIZE},
+    voxel::VoxelChunk,
+    world::ChunkCoord,
+};
+use std::{
+    hint::black_box,
+    time::{Duration, Instant},
+};
+
+const READS: usize = 1_000_000;
+const WRITES: usize = 100_000;
+
+/// Runs `f` until a quarter second has passed and returns the average time.
+fn time<T>(mut f: impl FnMut() -> T) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs == 0 || start.elapsed() < Duration::from_millis(250) {
+        black_box(f());
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+/// Cheap deterministic voxel positions, the same sequence for both layouts.
+fn positions(count: usize) -> Vec<[u32; 3]> {
+    let mut state = 1u32;
+    (0..count)
+        .map(|_| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            [0, 10, 20].map(|shift| (state >> shift) % CHUNK_SIZE)
+        })
+        .collect()
+}
+
+fn index([x, y, z]: [u32; 3]) -> usize {
+    (x + y * CHUNK_SIZE + z * CHUNK_SIZE * CHUNK_SIZE) as usize
+}
+
+fn bench(name: &str, raw: Vec<u8>) {
+    let reads = positions(READS);
+    let writes = positions(WRITES);
+    let packed = VoxelChunk::from_raw(CHUNK_SIZE, raw.clone());
+
+    println!(
+        "{name}: {} KiB raw, {} KiB packed at {} bits with {} palette entries",
+        raw.len() / 1024,
+        packed.packed().memory_usage() / 1024,
+        packed.packed().bits(),
+        packed.packed().palette().len()
+    );
+
+    let raw_pack = time(|| VoxelChunk::from_raw(CHUNK_SIZE, raw.clone()));
+    println!("  pack           {raw_pack:>12?}");
+
+    let raw_reads = time(|| reads.iter().map(|p| raw[index(*p)] as u32).sum::<u32>());
+    let packed_reads = time(|| {
+        reads
+            .iter()
+            .map(|[x, y, z]| packed.get(*x, *y, *z) as u32)
+            .sum::<u32>()
+    });
+    println!("  {READS} reads   raw {raw_reads:>12?}  packed {packed_reads:>12?}");
+
+    let raw_upload = time(|| raw.clone());
+    let packed_upload = time(|| packed.to_bytes());
+    println!("  upload bytes   raw {raw_upload:>12?}  packed {packed_upload:>12?}");
+
+    let raw_writes = time(|| {
+        let mut raw = raw.clone();
+        for (i, p) in writes.iter().enumerate() {
+            raw[index(*p)] = i as u8 % 4;
+        }
+        raw
+    });
+    let packed_writes = time(|| {
+        let mut packed = packed.clone();
+        for (i, [x, y, z]) in writes.iter().enumerate() {
+            packed.set(*x, *y, *z, i as u8 % 4);
+        }
+        packed
+    });
+    println!("  {WRITES} writes  raw {raw_writes:>12?}  packed {packed_writes:>12?}");
+}
+
+fn main() {
+    let coord = ChunkCoord::new(0, 0, 0);
+    bench("empty", vec![0; CHUNK_SIZE.pow(3) as usize]);
+    bench("flat", FlatGenerator::default().generate(coord).to_bytes());
+    bench("caves", CaveGenerator::new(5).generate(coord).to_bytes());
+    bench(
+        "heightmap",
+        HeightmapGenerator::new(5).generate(coord).to_bytes(),
+    );
+}
@@ -0,0 +1,74 @@
+//! Frame times of the occupancy mip traversal against the plain voxel DDA, rendered
+//! offscreen. On a software adapter this is all shader work.
+//!
+//! Run with `cargo bench -p engine --bench raymarch`.
+
+use engine::{
+    camera::Camera,
+    chunk_gen::{CaveGenerator, HeightmapGenerator, TerrainGenerator},
+    display_handler::OffscreenWindow,
+    Scene,
+};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const SIZE: u32 = 256;
+const FRAMES: u32 = 10;
+
+const POSES: &[(&str, [f32; 3], [f32; 3])] = &[
+    ("front", [0.0, 0.5, 3.0], [0.0, 0.0, 0.0]),
+    ("above", [0.5, 3.0, 0.5], [0.0, 0.0, 0.0]),
+    ("grazing", [3.0, 0.2, 0.4], [0.0, 0.1, 0.0]),
+];
+
+fn frame_time(target: &OffscreenWindow, scene: &mut Scene, camera: &Camera) -> Duration {
+    // the first frame pays for pipeline and texture setup
+    target.render(scene, camera);
+    let start = Instant::now();
+    for _ in 0..FRAMES {
+        target.render(scene, camera);
+    }
+    start.elapsed() / FRAMES
+}
+
+fn bench(target: &OffscreenWindow, name: &str, generator: Arc<dyn TerrainGenerator>) {
+    let mut scene = Scene::new(&target.device, &target.config, generator);
+    scene
+        .world
+        .generate_chunk(&target.device, &target.queue, (0, 0, 0).into());
+
+    println!("{name}:");
+    for (pose, eye, look_at) in POSES {
+        let mut camera = Camera::default(&target.config);
+        camera.eye = (*eye).into();
+        camera.target = (*look_at).into();
+
+        scene.settings.skip_empty_space = false;
+        let dda = frame_time(target, &mut scene, &camera);
+        scene.settings.skip_empty_space = true;
+        let mips = frame_time(target, &mut scene, &camera);
+
+        println!(
+            "  {pose:<8} dda {dda:>12?}  mips {mips:>12?}  {:.2}x",
+            dda.as_secs_f64() / mips.as_secs_f64()
+        );
+    }
+}
+
+fn main() {
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap();
+    let target = match runtime.block_on(OffscreenWindow::new(SIZE, SIZE, true)) {
+        Ok(target) => target,
+        Err(err) => {
+            eprintln!("no software adapter: {err}");
+            return;
+        }
+    };
+
+    bench(&target, "caves", Arc::new(CaveGenerator::new(5)));
+    bench(&target, "heightmap", Arc::new(HeightmapGenerator::new(5)));
+}
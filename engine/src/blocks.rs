@@ -0,0 +1,191 @@
+use crate::light_levels::MAX_LIGHT;
+
+pub type BlockId = u8;
+
+pub const AIR: BlockId = 0;
+pub const STONE: BlockId = 1;
+pub const DIRT: BlockId = 2;
+pub const GRASS: BlockId = 3;
+pub const SAND: BlockId = 4;
+pub const SNOW: BlockId = 5;
+pub const LAMP: BlockId = 6;
+pub const GLASS: BlockId = 7;
+pub const WATER: BlockId = 8;
+
+/// One palette entry per possible `R8Uint` voxel value.
+pub const MAX_BLOCKS: usize = 256;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Material {
+    pub color: [f32; 3],
+    pub emissive: [f32; 3],
+    pub roughness: f32,
+    /// 0 is fully opaque, 1 lets everything through. Anything above 0 is see-through
+    /// and only draws its surface at `1 - transparency`.
+    pub transparency: f32,
+    /// Tint light by how far it goes through the block, like water, rather than once
+    /// where it goes in, like glass.
+    pub translucent: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            color: [1.0, 1.0, 1.0],
+            emissive: [0.0, 0.0, 0.0],
+            roughness: 1.0,
+            transparency: 0.0,
+            translucent: false,
+        }
+    }
+}
+
+impl Material {
+    pub fn solid(color: [f32; 3]) -> Self {
+        Material {
+            color,
+            ..Default::default()
+        }
+    }
+
+    /// A block that gives off light of its own on top of what it reflects.
+    pub fn glowing(color: [f32; 3], emissive: [f32; 3]) -> Self {
+        Material {
+            color,
+            emissive,
+            ..Default::default()
+        }
+    }
+
+    /// See-through block that tints what's behind it by `color` once, however thick.
+    pub fn glass(color: [f32; 3], transparency: f32) -> Self {
+        Material {
+            color,
+            transparency,
+            ..Default::default()
+        }
+    }
+
+    /// See-through block that tints what's behind it more the deeper it is.
+    pub fn translucent(color: [f32; 3], transparency: f32) -> Self {
+        Material {
+            color,
+            transparency,
+            translucent: true,
+            ..Default::default()
+        }
+    }
+
+    /// Block light level the block gives off, from its brightest emissive channel.
+    pub fn light_level(&self) -> u8 {
+        let brightest = self.emissive.into_iter().fold(0.0, f32::max);
+        (brightest * MAX_LIGHT as f32)
+            .round()
+            .clamp(0.0, MAX_LIGHT as f32) as u8
+    }
+
+    pub fn to_raw(&self) -> MaterialRaw {
+        let [r, g, b] = self.color;
+        let [er, eg, eb] = self.emissive;
+        MaterialRaw {
+            color: [r, g, b, 1.0],
+            emissive: [er, eg, eb, 1.0],
+            roughness: self.roughness,
+            transparency: self.transparency,
+            translucent: self.translucent as u32,
+            _padding: 0.0,
+        }
+    }
+}
+
+/// Matches `Material` in `shader.wgsl`, std140 pads it to 48 bytes.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct MaterialRaw {
+    color: [f32; 4],
+    emissive: [f32; 4],
+    roughness: f32,
+    transparency: f32,
+    translucent: u32,
+    _padding: f32,
+}
+
+/// Maps voxel values to named materials, id 0 is always air.
+pub struct BlockRegistry {
+    blocks: Vec<(String, Material)>,
+}
+
+impl Default for BlockRegistry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        registry.register("stone", Material::solid([0.5, 0.5, 0.52]));
+        registry.register("dirt", Material::solid([0.45, 0.3, 0.18]));
+        registry.register("grass", Material::solid([0.3, 0.6, 0.2]));
+        registry.register("sand", Material::solid([0.86, 0.8, 0.55]));
+        registry.register("snow", Material::solid([0.95, 0.96, 1.0]));
+        registry.register("lamp", Material::glowing([1.0, 0.9, 0.6], [0.9, 0.7, 0.35]));
+        registry.register("glass", Material::glass([0.85, 0.95, 1.0], 0.8));
+        registry.register("water", Material::translucent([0.15, 0.45, 0.7], 0.85));
+        registry
+    }
+}
+
+impl BlockRegistry {
+    /// A registry that only knows air.
+    pub fn empty() -> Self {
+        Self {
+            blocks: vec![(
+                "air".to_string(),
+                Material {
+                    transparency: 1.0,
+                    ..Default::default()
+                },
+            )],
+        }
+    }
+
+    pub fn register(&mut self, name: &str, material: Material) -> BlockId {
+        assert!(self.blocks.len() < MAX_BLOCKS, "block palette is full");
+        self.blocks.push((name.to_string(), material));
+        (self.blocks.len() - 1) as BlockId
+    }
+
+    pub fn id(&self, name: &str) -> Option<BlockId> {
+        self.blocks
+            .iter()
+            .position(|(n, _)| n == name)
+            .map(|id| id as BlockId)
+    }
+
+    pub fn name(&self, id: BlockId) -> Option<&str> {
+        self.blocks.get(id as usize).map(|(name, _)| name.as_str())
+    }
+
+    pub fn material(&self, id: BlockId) -> Option<&Material> {
+        self.blocks.get(id as usize).map(|(_, material)| material)
+    }
+
+    pub fn material_mut(&mut self, id: BlockId) -> Option<&mut Material> {
+        self.blocks
+            .get_mut(id as usize)
+            .map(|(_, material)| material)
+    }
+
+    pub fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.blocks.is_empty()
+    }
+
+    /// The full palette as uploaded to the shader, unregistered ids are magenta.
+    pub fn to_raw(&self) -> Vec<MaterialRaw> {
+        let missing = Material::solid([1.0, 0.0, 1.0]).to_raw();
+        let mut palette = vec![missing; MAX_BLOCKS];
+        for (raw, (_, material)) in palette.iter_mut().zip(&self.blocks) {
+            *raw = material.to_raw();
+        }
+        palette
+    }
+}
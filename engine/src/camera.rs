@@ -1,12 +1,18 @@
-use cgmath::Vector3;
-use winit::{event::*, keyboard::PhysicalKey};
-
+use cgmath::{EuclideanSpace, InnerSpace, SquareMatrix, Vector3, Vector4};
+use winit::{
+    dpi::{PhysicalPosition, PhysicalSize},
+    event::*,
+    keyboard::PhysicalKey,
+};
+
+/// Maps OpenGL's -1..1 clip depth to wgpu's 0..1. `Matrix4::new` takes columns, so
+/// this reads transposed.
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
 pub struct Camera {
@@ -23,7 +29,41 @@ impl Camera {
     pub fn build_view_projection_matrix(&self) -> cgmath::Matrix4<f32> {
         let view = cgmath::Matrix4::look_at_rh(self.eye, self.target, self.up);
         let proj = cgmath::perspective(cgmath::Deg(self.fovy), self.aspect, self.znear, self.zfar);
-        return OPENGL_TO_WGPU_MATRIX * proj * view;
+        OPENGL_TO_WGPU_MATRIX * proj * view
+    }
+
+    /// Ray from the eye through a point on screen, `x` and `y` in -1..1 with +y up.
+    pub fn screen_ray(&self, x: f32, y: f32) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+        let inverse = self
+            .build_view_projection_matrix()
+            .invert()
+            .expect("view projection is not invertible");
+        let unproject = |depth: f32| {
+            let p = inverse * Vector4::new(x, y, depth, 1.0);
+            cgmath::Point3::from_vec(p.truncate() / p.w)
+        };
+        // both points sit on the pixel's line of sight, but not necessarily in
+        // front of the eye, so orient the result along the view direction
+        let dir = (unproject(1.0) - unproject(0.0)).normalize();
+        if dir.dot(self.target - self.eye) < 0.0 {
+            (self.eye, -dir)
+        } else {
+            (self.eye, dir)
+        }
+    }
+
+    pub fn centre_ray(&self) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+        self.screen_ray(0.0, 0.0)
+    }
+
+    pub fn cursor_ray(
+        &self,
+        cursor: PhysicalPosition<f64>,
+        size: PhysicalSize<u32>,
+    ) -> (cgmath::Point3<f32>, cgmath::Vector3<f32>) {
+        let x = cursor.x as f32 / size.width.max(1) as f32 * 2.0 - 1.0;
+        let y = 1.0 - cursor.y as f32 / size.height.max(1) as f32 * 2.0;
+        self.screen_ray(x, y)
     }
 
     pub fn default(config: &wgpu::SurfaceConfiguration) -> Self {
@@ -42,7 +82,6 @@ impl Camera {
 pub struct CameraController {
     sensitivity: f32,
     mouse_input: [f64; 2],
-    zoom_dis: f32,
     move_direction: cgmath::Vector3<f32>,
 
     w_key: bool,
@@ -64,7 +103,6 @@ impl CameraController {
         Self {
             sensitivity,
             mouse_input: [0.0, 0.0],
-            zoom_dis: 2.0,
             move_direction: cgmath::Vector3::new(0.0, 0.0, 0.0),
 
             w_key: false,
@@ -86,22 +124,11 @@ impl CameraController {
                 ];
             }
 
-            DeviceEvent::MouseWheel { delta, .. } => match delta {
-                MouseScrollDelta::LineDelta(_x, y) => {
-                    self.zoom_dis = (self.zoom_dis - y / 10.0).max(1.0);
-                }
-                _ => {}
-            },
-
             DeviceEvent::Key(RawKeyEvent {
                 physical_key,
                 state,
             }) => {
-                let change = if state == &ElementState::Pressed {
-                    true
-                } else {
-                    false
-                };
+                let change = state == &ElementState::Pressed;
                 use winit::keyboard::KeyCode;
 
                 match physical_key {
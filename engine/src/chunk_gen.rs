@@ -1,79 +1,406 @@
-use crate::texture::Texture;
-use noise::{NoiseFn, SuperSimplex};
+use crate::{
+    blocks::{self, BlockId},
+    voxel::VoxelChunk,
+    world::ChunkCoord,
+};
+use noise::{Fbm, MultiFractal, NoiseFn, RidgedMulti, SuperSimplex};
+use std::{
+    collections::HashMap,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
 
-const CHUNK_SIZE: u32 = 100;
+pub const CHUNK_SIZE: u32 = 100;
 const NOISE_SIZE: f64 = 50.0;
+/// How far below an air voxel the ground is still dirt instead of stone.
+const DIRT_DEPTH: i32 = 3;
 
-pub fn generate_chunk(device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
-    let total_blocks = CHUNK_SIZE.pow(3);
-    let noise = SuperSimplex::new(5);
-    let mut result: Vec<u8> = Vec::with_capacity(total_blocks as usize);
-
-    for i in 0..total_blocks {
-        let x = i % CHUNK_SIZE;
-        let y = (i / CHUNK_SIZE) % CHUNK_SIZE;
-        let z = (i / CHUNK_SIZE.pow(2)) % CHUNK_SIZE;
-
-        let val = noise.get([
-            x as f64 / NOISE_SIZE,
-            y as f64 / NOISE_SIZE,
-            z as f64 / NOISE_SIZE,
-        ]);
-
-        if val > 0.0 {
-            result.push(255);
-        } else {
-            result.push(0);
-        }
+/// Produces the voxels of one chunk. Called from the streaming worker threads,
+/// so it has to be pure CPU work and shareable.
+pub trait TerrainGenerator: Send + Sync {
+    fn generate(&self, coord: ChunkCoord) -> VoxelChunk;
+
+    /// Stable name used to pick the generator at startup, `from_name` turns it back
+    /// into the same generator.
+    fn id(&self) -> String;
+
+    /// Seed the generator was built from, 0 for ones that don't use any.
+    fn seed(&self) -> u32 {
+        0
     }
+}
 
-    let size = wgpu::Extent3d {
-        width: CHUNK_SIZE,
-        height: CHUNK_SIZE,
-        depth_or_array_layers: CHUNK_SIZE,
+/// Looks up a generator by the name given on the command line. `file:<dir>` loads
+/// raw chunk dumps from a directory and fails if any of them can't be used.
+pub fn from_name(name: &str, seed: u32) -> io::Result<Arc<dyn TerrainGenerator>> {
+    let generator: Arc<dyn TerrainGenerator> = match name {
+        "caves" => Arc::new(CaveGenerator::new(seed)),
+        "heightmap" => Arc::new(HeightmapGenerator::new(seed)),
+        "flat" => Arc::new(FlatGenerator::default()),
+        _ => match name.strip_prefix("file:") {
+            Some(dir) => Arc::new(FileGenerator::new(dir)?),
+            None => {
+                return Err(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    format!("unknown terrain generator {name:?}"),
+                ))
+            }
+        },
     };
+    Ok(generator)
+}
 
-    let texture = device.create_texture(&wgpu::TextureDescriptor {
-        label: None,
-        size,
-        mip_level_count: 1,
-        sample_count: 1,
-        dimension: wgpu::TextureDimension::D3,
-        format: wgpu::TextureFormat::R8Uint,
-        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
-        view_formats: &[],
-    });
-
-    queue.write_texture(
-        wgpu::ImageCopyTexture {
-            aspect: wgpu::TextureAspect::All,
-            texture: &texture,
-            mip_level: 0,
-            origin: wgpu::Origin3d::ZERO,
-        },
-        &result,
-        wgpu::ImageDataLayout {
-            offset: 0,
-            bytes_per_row: Some(CHUNK_SIZE),
-            rows_per_image: Some(CHUNK_SIZE),
-        },
-        size,
-    );
-
-    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
-    let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
-        address_mode_u: wgpu::AddressMode::ClampToEdge,
-        address_mode_v: wgpu::AddressMode::ClampToEdge,
-        address_mode_w: wgpu::AddressMode::ClampToEdge,
-        mag_filter: wgpu::FilterMode::Nearest,
-        min_filter: wgpu::FilterMode::Nearest,
-        mipmap_filter: wgpu::FilterMode::Nearest,
-        ..Default::default()
-    });
-
-    Texture {
-        view,
-        sampler,
-        texture,
+/// Top blocks of a column, anything deeper than `depth` below air is stone.
+#[derive(Debug, Clone, Copy)]
+pub struct Layers {
+    pub surface: BlockId,
+    pub subsurface: BlockId,
+    pub depth: i32,
+}
+
+impl Default for Layers {
+    fn default() -> Self {
+        Self {
+            surface: blocks::GRASS,
+            subsurface: blocks::DIRT,
+            depth: DIRT_DEPTH,
+        }
+    }
+}
+
+/// Walks every column top down and layers it by the depth below the last air
+/// voxel. Both closures take world voxel coordinates.
+fn layered_chunk(
+    coord: ChunkCoord,
+    is_solid: impl Fn(i32, i32, i32) -> bool,
+    layers: impl Fn(i32, i32) -> Layers,
+) -> VoxelChunk {
+    let mut result = vec![blocks::AIR; CHUNK_SIZE.pow(3) as usize];
+    let offset = coord * CHUNK_SIZE as i32;
+    let size = CHUNK_SIZE as i32;
+
+    for z in 0..size {
+        for x in 0..size {
+            let layers = layers(x + offset.x, z + offset.z);
+
+            // start a few voxels above the chunk so the surface layers carry on
+            // across the chunk border
+            let mut depth = 0;
+            for y in (0..size + layers.depth).rev() {
+                depth = if is_solid(x + offset.x, y + offset.y, z + offset.z) {
+                    depth + 1
+                } else {
+                    0
+                };
+                if y >= size || depth == 0 {
+                    continue;
+                }
+
+                let block = match depth {
+                    1 => layers.surface,
+                    d if d <= layers.depth => layers.subsurface,
+                    _ => blocks::STONE,
+                };
+                result[(x + y * size + z * size * size) as usize] = block;
+            }
+        }
+    }
+
+    VoxelChunk::from_raw(CHUNK_SIZE, result)
+}
+
+/// Thresholded 3D simplex noise, a world of floating blobs and caves.
+pub struct CaveGenerator {
+    noise: SuperSimplex,
+    seed: u32,
+}
+
+impl CaveGenerator {
+    pub fn new(seed: u32) -> Self {
+        Self {
+            noise: SuperSimplex::new(seed),
+            seed,
+        }
+    }
+}
+
+impl TerrainGenerator for CaveGenerator {
+    fn generate(&self, coord: ChunkCoord) -> VoxelChunk {
+        layered_chunk(
+            coord,
+            |x, y, z| {
+                self.noise.get([
+                    x as f64 / NOISE_SIZE,
+                    y as f64 / NOISE_SIZE,
+                    z as f64 / NOISE_SIZE,
+                ]) > 0.0
+            },
+            |_, _| Layers::default(),
+        )
+    }
+
+    fn id(&self) -> String {
+        "caves".to_string()
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Tundra,
+    Mountains,
+}
+
+impl Biome {
+    pub fn layers(&self) -> Layers {
+        match self {
+            Biome::Plains => Layers::default(),
+            Biome::Desert => Layers {
+                surface: blocks::SAND,
+                subsurface: blocks::SAND,
+                depth: 6,
+            },
+            Biome::Tundra => Layers {
+                surface: blocks::SNOW,
+                ..Default::default()
+            },
+            Biome::Mountains => Layers {
+                surface: blocks::STONE,
+                subsurface: blocks::STONE,
+                depth: 1,
+            },
+        }
+    }
+}
+
+/// 2D heightmap terrain: fBm hills with ridged multifractal mountain ranges, and
+/// biomes picked from separate temperature and humidity noise.
+pub struct HeightmapGenerator {
+    hills: Fbm<SuperSimplex>,
+    ridges: RidgedMulti<SuperSimplex>,
+    mountain_mask: Fbm<SuperSimplex>,
+    temperature: Fbm<SuperSimplex>,
+    humidity: Fbm<SuperSimplex>,
+    pub base_height: f64,
+    pub hill_height: f64,
+    pub mountain_height: f64,
+    /// World height at which the peaks start to be snowed over.
+    pub snow_line: f64,
+    seed: u32,
+}
+
+impl HeightmapGenerator {
+    pub fn new(seed: u32) -> Self {
+        // every layer gets its own seed so they don't line up with each other
+        Self {
+            hills: Fbm::new(seed).set_octaves(5).set_frequency(1.0 / 160.0),
+            ridges: RidgedMulti::new(seed.wrapping_add(1))
+                .set_octaves(5)
+                .set_frequency(1.0 / 220.0),
+            mountain_mask: Fbm::new(seed.wrapping_add(2))
+                .set_octaves(2)
+                .set_frequency(1.0 / 600.0),
+            temperature: Fbm::new(seed.wrapping_add(3))
+                .set_octaves(3)
+                .set_frequency(1.0 / 900.0),
+            humidity: Fbm::new(seed.wrapping_add(4))
+                .set_octaves(3)
+                .set_frequency(1.0 / 700.0),
+            base_height: 35.0,
+            hill_height: 12.0,
+            mountain_height: 55.0,
+            snow_line: 80.0,
+            seed,
+        }
+    }
+
+    /// How much of the ridged noise shows through, 0 on flat land and 1 in ranges.
+    fn mountains(&self, x: f64, z: f64) -> f64 {
+        ((self.mountain_mask.get([x, z]) - 0.1) * 3.0).clamp(0.0, 1.0)
+    }
+
+    pub fn height_at(&self, x: i32, z: i32) -> f64 {
+        let (x, z) = (x as f64, z as f64);
+        let hills = self.hills.get([x, z]) * self.hill_height;
+        // ridged output sits roughly in -1..1 with sharp crests at the top
+        let ridges = (self.ridges.get([x, z]) * 0.5 + 0.5) * self.mountain_height;
+        self.base_height + hills + ridges * self.mountains(x, z)
+    }
+
+    pub fn biome_at(&self, x: i32, z: i32) -> Biome {
+        self.biome(x, z, self.height_at(x, z))
+    }
+
+    fn biome(&self, x: i32, z: i32, height: f64) -> Biome {
+        let (x, z) = (x as f64, z as f64);
+
+        if self.mountains(x, z) > 0.5 {
+            return Biome::Mountains;
+        }
+
+        // it gets colder further up
+        let temperature = self.temperature.get([x, z]) - (height - self.base_height) / 150.0;
+        let humidity = self.humidity.get([x, z]);
+        match (temperature, humidity) {
+            (t, _) if t < -0.25 => Biome::Tundra,
+            (t, h) if t > 0.2 && h < 0.0 => Biome::Desert,
+            _ => Biome::Plains,
+        }
+    }
+
+    fn layers_at(&self, x: i32, z: i32, height: f64) -> Layers {
+        let layers = self.biome(x, z, height).layers();
+        if height > self.snow_line {
+            return Layers {
+                surface: blocks::SNOW,
+                ..layers
+            };
+        }
+        layers
+    }
+}
+
+impl TerrainGenerator for HeightmapGenerator {
+    fn generate(&self, coord: ChunkCoord) -> VoxelChunk {
+        // the column values are needed for every voxel, so work them out once
+        let size = CHUNK_SIZE as i32;
+        let offset = coord * size;
+        let mut heights = Vec::with_capacity((size * size) as usize);
+        let mut layers = Vec::with_capacity((size * size) as usize);
+        for z in offset.z..offset.z + size {
+            for x in offset.x..offset.x + size {
+                let height = self.height_at(x, z);
+                heights.push(height);
+                layers.push(self.layers_at(x, z, height));
+            }
+        }
+        let column = |x: i32, z: i32| ((x - offset.x) + (z - offset.z) * size) as usize;
+
+        layered_chunk(
+            coord,
+            |x, y, z| (y as f64) < heights[column(x, z)],
+            |x, z| layers[column(x, z)],
+        )
+    }
+
+    fn id(&self) -> String {
+        "heightmap".to_string()
+    }
+
+    fn seed(&self) -> u32 {
+        self.seed
+    }
+}
+
+/// Solid ground below `height`, nothing above.
+pub struct FlatGenerator {
+    pub height: i32,
+}
+
+impl Default for FlatGenerator {
+    fn default() -> Self {
+        Self {
+            height: CHUNK_SIZE as i32 / 2,
+        }
+    }
+}
+
+impl TerrainGenerator for FlatGenerator {
+    fn generate(&self, coord: ChunkCoord) -> VoxelChunk {
+        layered_chunk(coord, |_, y, _| y < self.height, |_, _| Layers::default())
+    }
+
+    fn id(&self) -> String {
+        "flat".to_string()
+    }
+}
+
+/// Chunks from `<dir>/<x>_<y>_<z>.chunk` files holding `CHUNK_SIZE`³ raw block ids,
+/// chunks without a file are left empty. Every file is read and checked once by
+/// `new`, so a bad one is reported there rather than on the worker threads.
+pub struct FileGenerator {
+    dir: PathBuf,
+    chunks: HashMap<ChunkCoord, VoxelChunk>,
+}
+
+impl FileGenerator {
+    /// Loads every `.chunk` file in `dir`, failing on the first one that's misnamed,
+    /// unreadable or the wrong size. Other files are ignored.
+    ///
+    /// `dir` is made absolute so `id` finds it again from any working directory,
+    /// and has to be utf-8 to be written into an id at all.
+    pub fn new(dir: impl Into<PathBuf>) -> io::Result<Self> {
+        let dir = std::fs::canonicalize(dir.into())?;
+        if dir.to_str().is_none() {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!("{} isn't a utf-8 path", dir.display()),
+            ));
+        }
+        let mut chunks = HashMap::new();
+        for entry in std::fs::read_dir(&dir)? {
+            let path = entry?.path();
+            if path.extension().is_none_or(|ext| ext != "chunk") {
+                continue;
+            }
+            let (coord, chunk) = Self::read_chunk(&path)
+                .map_err(|err| io::Error::new(err.kind(), format!("{}: {err}", path.display())))?;
+            chunks.insert(coord, chunk);
+        }
+        Ok(Self { dir, chunks })
+    }
+
+    fn read_chunk(path: &Path) -> io::Result<(ChunkCoord, VoxelChunk)> {
+        let coord = path
+            .file_stem()
+            .and_then(|stem| stem.to_str())
+            .and_then(|stem| {
+                let parts = stem
+                    .split('_')
+                    .map(|v| v.parse().ok())
+                    .collect::<Option<Vec<i32>>>()?;
+                let [x, y, z] = parts[..] else {
+                    return None;
+                };
+                Some(ChunkCoord::new(x, y, z))
+            })
+            .ok_or_else(|| {
+                io::Error::new(ErrorKind::InvalidData, "name isn't <x>_<y>_<z>.chunk")
+            })?;
+
+        let data = std::fs::read(path)?;
+        let expected = CHUNK_SIZE.pow(3) as usize;
+        if data.len() != expected {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                format!("{} bytes, expected {expected}", data.len()),
+            ));
+        }
+        Ok((coord, VoxelChunk::from_raw(CHUNK_SIZE, data)))
+    }
+
+    pub fn chunk_path(&self, coord: ChunkCoord) -> PathBuf {
+        self.dir
+            .join(format!("{}_{}_{}.chunk", coord.x, coord.y, coord.z))
+    }
+}
+
+impl TerrainGenerator for FileGenerator {
+    fn generate(&self, coord: ChunkCoord) -> VoxelChunk {
+        self.chunks
+            .get(&coord)
+            .cloned()
+            .unwrap_or_else(|| VoxelChunk::new(CHUNK_SIZE))
+    }
+
+    fn id(&self) -> String {
+        // `new` only takes utf-8 paths, so this is lossless
+        format!("file:{}", self.dir.display())
     }
 }
@@ -1,9 +1,12 @@
+use crate::{camera::Camera, Scene};
 use std::path::Path;
 use winit::{
     event_loop::EventLoop,
     window::{Icon, Window},
 };
 
+pub const WINDOW_TITLE: &str = "Voxel Game Engine";
+
 pub struct GameWindow {
     pub window: Window,
     pub event_loop: EventLoop<()>,
@@ -29,13 +32,125 @@ impl GameWindow {
     }
 }
 
+/// Renders into a texture instead of a window, for machines without a display.
+pub struct OffscreenWindow {
+    pub queue: wgpu::Queue,
+    pub device: wgpu::Device,
+    pub adapter: wgpu::Adapter,
+    pub config: wgpu::SurfaceConfiguration,
+    pub texture: wgpu::Texture,
+}
+
+impl OffscreenWindow {
+    pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+
+    pub async fn new(
+        width: u32,
+        height: u32,
+        force_fallback_adapter: bool,
+    ) -> Result<Self, String> {
+        let instance = wgpu::Instance::default();
+        let adapter = instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: wgpu::PowerPreference::HighPerformance,
+                force_fallback_adapter,
+                compatible_surface: None,
+            })
+            .await
+            .ok_or("Cant create adapter")?;
+        let (device, queue) = request_device(&adapter)
+            .await
+            .map_err(|err| format!("Cant create device: {err}"))?;
+
+        // never handed to a surface, but the camera and depth texture are sized from it
+        let config = wgpu::SurfaceConfiguration {
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            format: Self::FORMAT,
+            width: width.max(1),
+            height: height.max(1),
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            view_formats: vec![],
+        };
+
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("offscreen_target"),
+            size: wgpu::Extent3d {
+                width: config.width,
+                height: config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: Self::FORMAT,
+            usage: config.usage,
+            view_formats: &[],
+        });
+
+        Ok(OffscreenWindow {
+            queue,
+            device,
+            adapter,
+            config,
+            texture,
+        })
+    }
+
+    /// Draws one frame of `scene` and copies it back to the CPU.
+    pub fn render(&self, scene: &mut Scene, camera: &Camera) -> image::RgbaImage {
+        let view = self
+            .texture
+            .create_view(&wgpu::TextureViewDescriptor::default());
+        scene.render(&self.device, &self.queue, &view, camera);
+
+        let (width, height) = (self.config.width, self.config.height);
+        let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+        let padded_row = (width * 4).div_ceil(align) * align;
+
+        let output = self.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("offscreen_readback"),
+            size: (padded_row * height) as u64,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.copy_texture_to_buffer(
+            self.texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &output,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row),
+                    rows_per_image: Some(height),
+                },
+            },
+            self.texture.size(),
+        );
+        self.queue.submit(Some(encoder.finish()));
+
+        let slice = output.slice(..);
+        slice.map_async(wgpu::MapMode::Read, |res| {
+            res.expect("Failed to map readback buffer")
+        });
+        self.device.poll(wgpu::Maintain::Wait);
+
+        let mut pixels = Vec::with_capacity((width * height * 4) as usize);
+        for row in slice.get_mapped_range().chunks(padded_row as usize) {
+            pixels.extend_from_slice(&row[..(width * 4) as usize]);
+        }
+        output.unmap();
+
+        image::RgbaImage::from_raw(width, height, pixels).expect("readback size mismatch")
+    }
+}
+
 async fn create_surface(
     window: &Window,
 ) -> (wgpu::Device, wgpu::Queue, wgpu::Adapter, wgpu::Surface) {
-    let mut size = window.inner_size();
-    size.width = size.width.max(1);
-    size.height = size.height.max(1);
-
     let instance = wgpu::Instance::default();
 
     let surface = unsafe { instance.create_surface(&window).unwrap() };
@@ -48,20 +163,39 @@ async fn create_surface(
         .await
         .expect("Cant create adapter");
 
-    let (device, queue) = adapter
+    let (device, queue) = request_device(&adapter).await.expect("cant create device");
+
+    (device, queue, adapter, surface)
+}
+
+/// Storage buffers and compute passes where the adapter has them, see `Scene::new`
+/// and `World::new`, the WebGL2 limits everywhere else.
+fn device_limits(adapter: &wgpu::Adapter) -> wgpu::Limits {
+    let flags = adapter.get_downlevel_capabilities().flags;
+    let compute = wgpu::Limits::downlevel_defaults();
+    if flags
+        .contains(wgpu::DownlevelFlags::COMPUTE_SHADERS | wgpu::DownlevelFlags::FRAGMENT_STORAGE)
+        && compute.check_limits(&adapter.limits())
+    {
+        compute.using_resolution(adapter.limits())
+    } else {
+        wgpu::Limits::downlevel_webgl2_defaults().using_resolution(adapter.limits())
+    }
+}
+
+async fn request_device(
+    adapter: &wgpu::Adapter,
+) -> Result<(wgpu::Device, wgpu::Queue), wgpu::RequestDeviceError> {
+    adapter
         .request_device(
             &wgpu::DeviceDescriptor {
                 label: Some("device"),
                 features: wgpu::Features::empty(),
-                limits: wgpu::Limits::downlevel_webgl2_defaults()
-                    .using_resolution(adapter.limits()),
+                limits: device_limits(adapter),
             },
             None,
         )
         .await
-        .expect("cant create device");
-
-    (device, queue, adapter, surface)
 }
 
 pub async fn create_window() -> (Window, EventLoop<()>) {
@@ -70,7 +204,7 @@ pub async fn create_window() -> (Window, EventLoop<()>) {
     println!("{}", icon_path);
 
     let builder = winit::window::WindowBuilder::new()
-        .with_title("Voxel Game Engine")
+        .with_title(WINDOW_TITLE)
         .with_window_icon(Some(load_icon(std::path::Path::new(icon_path))))
         .with_theme(Some(winit::window::Theme::Dark));
     let window = builder.build(&event_loop).unwrap();
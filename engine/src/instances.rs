@@ -1,6 +1,6 @@
 use crate::Storrage;
 use core::f32;
-use std::{rc::Rc, vec};
+use std::vec;
 use wgpu::util::DeviceExt;
 
 #[repr(C)]
@@ -126,22 +126,6 @@ impl Mesh {
             usage: wgpu::BufferUsages::INDEX,
         });
 
-        let cframe_rc = Rc::new(self.cframe.clone());
-        store.instances.push(*cframe_rc);
-
-        let instance_data = store
-            .instances
-            .iter()
-            .map(|v| v.to_raw())
-            .collect::<Vec<_>>();
-
-        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-            label: Some("Index Buffer"),
-            contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-        });
-
-        store.instance_buffer = instance_buffer;
         store.vertex_buffer = vertex_buffer;
         store.index_buffer = index_buffer;
     }
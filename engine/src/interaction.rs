@@ -0,0 +1,132 @@
+use crate::{
+    blocks::{self, BlockId},
+    camera::Camera,
+    raycast,
+    world::World,
+};
+use winit::event::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Action {
+    Break,
+    Place,
+}
+
+/// Left click breaks the block under the crosshair, right click places the
+/// hotbar block picked with the scroll wheel.
+pub struct BlockInteraction {
+    hotbar: Vec<BlockId>,
+    selected: usize,
+    /// How far away blocks can be edited, in world units.
+    pub reach: f32,
+    scroll: f64,
+    pending: Option<Action>,
+}
+
+impl Default for BlockInteraction {
+    fn default() -> Self {
+        Self::new(vec![
+            blocks::STONE,
+            blocks::DIRT,
+            blocks::GRASS,
+            blocks::SAND,
+            blocks::SNOW,
+            blocks::LAMP,
+            blocks::GLASS,
+            blocks::WATER,
+        ])
+    }
+}
+
+impl BlockInteraction {
+    /// Starts with the first slot of `hotbar` selected.
+    ///
+    /// # Panics
+    ///
+    /// If `hotbar` is empty, there has to be something to place.
+    pub fn new(hotbar: Vec<BlockId>) -> Self {
+        assert!(!hotbar.is_empty(), "the hotbar needs at least one block");
+        Self {
+            hotbar,
+            selected: 0,
+            reach: 6.0,
+            scroll: 0.0,
+            pending: None,
+        }
+    }
+
+    pub fn hotbar(&self) -> &[BlockId] {
+        &self.hotbar
+    }
+
+    /// Index of the selected hotbar slot.
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Selects hotbar slot `slot`, wrapping around past the last one.
+    pub fn select(&mut self, slot: usize) {
+        self.selected = slot % self.hotbar.len();
+    }
+
+    pub fn selected_block(&self) -> BlockId {
+        self.hotbar[self.selected]
+    }
+
+    pub fn process_events(&mut self, event: &DeviceEvent) {
+        let lines = match event {
+            DeviceEvent::MouseWheel {
+                delta: MouseScrollDelta::LineDelta(_x, y),
+            } => *y as f64,
+            DeviceEvent::MouseWheel {
+                delta: MouseScrollDelta::PixelDelta(pos),
+            } => pos.y / 40.0,
+            _ => return,
+        };
+
+        // touchpads send lots of small deltas, only move once they add up to a line
+        self.scroll += lines;
+        let slots = self.hotbar.len() as i64;
+        let steps = self.scroll.trunc() as i64;
+        self.scroll -= steps as f64;
+        self.selected = (self.selected as i64 - steps).rem_euclid(slots) as usize;
+    }
+
+    pub fn process_window_events(&mut self, event: &WindowEvent) {
+        if let WindowEvent::MouseInput {
+            state: ElementState::Pressed,
+            button,
+            ..
+        } = event
+        {
+            match button {
+                MouseButton::Left => self.pending = Some(Action::Break),
+                MouseButton::Right => self.pending = Some(Action::Place),
+                _ => {}
+            }
+        }
+    }
+
+    /// Applies the click from this frame, if any, to the block the camera looks at.
+    pub fn update(&mut self, world: &mut World, camera: &Camera) {
+        let Some(action) = self.pending.take() else {
+            return;
+        };
+
+        let (origin, dir) = camera.centre_ray();
+        let Some(hit) = raycast::raycast(world, origin, dir, self.reach) else {
+            return;
+        };
+
+        match action {
+            Action::Break => {
+                world.set_voxel(hit.voxel, blocks::AIR);
+            }
+            // a zero normal means the ray started inside the block, no face to build on
+            Action::Place if hit.normal != cgmath::Vector3::new(0, 0, 0) => {
+                world.set_voxel(hit.adjacent(), self.selected_block());
+            }
+            Action::Place => {}
+        }
+    }
+}
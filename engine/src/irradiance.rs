@@ -0,0 +1,721 @@
+use crate::{
+    blocks::{BlockRegistry, MAX_BLOCKS},
+    light_levels::{BlockLight, NEIGHBOURS},
+    lighting::Lighting,
+    texture::Texture,
+    voxel::{VoxelChunk, VoxelRegion},
+};
+use cgmath::{InnerSpace, Vector3};
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+
+/// Probes along each axis of a chunk unless `World::set_probe_resolution` says
+/// otherwise, one about every 6 voxels.
+pub const DEFAULT_PROBE_RESOLUTION: u32 = 16;
+/// Rays every probe gathers light with.
+const PROBE_RAYS: usize = 32;
+/// How far probe rays, and the shadow rays from where they land, go in voxels. An
+/// edit can only change the probes within twice this of it.
+const PROBE_RAY_LENGTH: f32 = 16.0;
+/// The chunks along each side of one in `NEIGHBOURS` order, for the probe rays that
+/// leave it. `None` where nothing is loaded, which counts as air.
+pub type Neighbours = [Option<Arc<VoxelChunk>>; 6];
+
+/// Same as `TINT_DEPTH` in the shader, voxels of a translucent block it takes to tint
+/// light by the block's colour once.
+const TINT_DEPTH: f32 = 10.0;
+
+/// How a see-through block lets light through, the same way the shader blends what
+/// view rays see behind it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Filter {
+    transparency: f32,
+    color: [f32; 3],
+    translucent: bool,
+}
+
+impl Filter {
+    /// Light left after going in through the block's surface.
+    fn surface(&self) -> [f32; 3] {
+        if self.translucent {
+            [self.transparency; 3]
+        } else {
+            self.color.map(|c| c * self.transparency)
+        }
+    }
+
+    /// Light left after going `distance` voxels through the block.
+    fn depth(&self, distance: f32) -> [f32; 3] {
+        if self.translucent {
+            self.color.map(|c| c.powf(distance / TINT_DEPTH))
+        } else {
+            [1.0; 3]
+        }
+    }
+}
+
+fn tint(light: &mut [f32; 3], filter: [f32; 3]) {
+    (0..3).for_each(|i| light[i] *= filter[i]);
+}
+
+/// What the probes and light levels are lit with. Chunks lit with a different one
+/// are stale.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BounceLight {
+    /// Towards the sun, normalized.
+    sun_direction: Vector3<f32>,
+    sun_color: [f32; 3],
+    /// Albedo and emissive colour of every block id.
+    materials: Vec<([f32; 3], [f32; 3])>,
+    /// What every see-through block id does to light going through it, `None` for
+    /// air and opaque blocks.
+    filters: Vec<Option<Filter>>,
+    /// What the blocks mean for `LightLevels`.
+    block_light: BlockLight,
+}
+
+impl BounceLight {
+    pub fn new(lighting: &Lighting, blocks: &BlockRegistry) -> Self {
+        let materials = (0..MAX_BLOCKS)
+            .map(|id| {
+                blocks
+                    .material(id as u8)
+                    .map_or(([0.0; 3], [0.0; 3]), |m| (m.color, m.emissive))
+            })
+            .collect();
+        let filters = (0..MAX_BLOCKS)
+            .map(|id| {
+                let material = blocks.material(id as u8).filter(|m| m.transparency > 0.0)?;
+                Some(Filter {
+                    transparency: material.transparency,
+                    color: material.color,
+                    translucent: material.translucent,
+                })
+            })
+            .collect();
+
+        Self {
+            sun_direction: lighting.sun_direction.normalize(),
+            sun_color: lighting.sun_color,
+            materials,
+            filters,
+            block_light: BlockLight::new(blocks),
+        }
+    }
+
+    pub fn block_light(&self) -> &BlockLight {
+        &self.block_light
+    }
+
+    /// Whether light gets through block `id`.
+    fn is_clear(&self, id: u8) -> bool {
+        id == 0 || self.filters[id as usize].is_some()
+    }
+
+    /// Light leaving the face of block `id` that looks along `normal`. The sun light
+    /// is black if something opaque in `voxels` shades it, and tinted by the
+    /// see-through blocks it comes through.
+    fn radiance(
+        &self,
+        voxels: &Voxels,
+        id: u8,
+        pos: Vector3<f32>,
+        normal: Vector3<f32>,
+    ) -> [f32; 3] {
+        let (albedo, emissive) = self.materials[id as usize];
+        let facing = normal.dot(self.sun_direction).max(0.0);
+        let mut sun = [facing; 3];
+        if facing > 0.0 {
+            let (blocker, filter) = voxels.march(pos + normal * 1e-3, self.sun_direction, self);
+            sun = match blocker {
+                Some(_) => [0.0; 3],
+                None => filter.map(|v| v * facing),
+            };
+        }
+        [0, 1, 2].map(|i| emissive[i] + albedo[i] * self.sun_color[i] * sun[i])
+    }
+}
+
+/// The block a ray stopped at, where it went in and the face it went in through.
+type Hit = (u8, Vector3<f32>, Vector3<f32>);
+
+/// A chunk's block ids unpacked for the many short rays of a bake, and the chunks
+/// around it for the rays that leave it.
+struct Voxels<'a> {
+    size: u32,
+    data: Vec<u8>,
+    neighbours: &'a Neighbours,
+}
+
+impl Voxels<'_> {
+    /// The block at `voxel`, counted from the chunk's corner. Past its edges and
+    /// corners, where only one of the chunks diagonally across could say, it's air.
+    fn get(&self, voxel: Vector3<i32>) -> u8 {
+        let size = self.size as i32;
+        let mut outside = (0..3).filter(|&i| !(0..size).contains(&voxel[i]));
+        let Some(axis) = outside.next() else {
+            return self.data[(voxel.x + voxel.y * size + voxel.z * size * size) as usize];
+        };
+        if outside.next().is_some() {
+            return 0;
+        }
+
+        let mut offset = [0; 3];
+        offset[axis] = voxel[axis].signum();
+        let side = NEIGHBOURS.iter().position(|n| *n == offset).unwrap();
+        let local = voxel - Vector3::from(offset) * size;
+        match &self.neighbours[side] {
+            Some(chunk) if (0..size).contains(&local[axis]) => {
+                let [x, y, z] = [local.x, local.y, local.z].map(|v| v as u32);
+                chunk.get(x, y, z)
+            }
+            _ => 0,
+        }
+    }
+
+    /// Steps voxel by voxel from `origin` for up to `PROBE_RAY_LENGTH`, returning the
+    /// first opaque block, where the ray entered it and the face it entered through.
+    /// See-through blocks are gone through, along with the filter of what's left of
+    /// light coming the other way past them.
+    fn march(
+        &self,
+        origin: Vector3<f32>,
+        dir: Vector3<f32>,
+        light: &BounceLight,
+    ) -> (Option<Hit>, [f32; 3]) {
+        // axis-aligned rays would divide by zero, same nudge as the shader's `new_ray`
+        let dir = dir.map(|v| if v.abs() < 1e-6 { 1e-6 } else { v });
+        let mut voxel = origin.map(|v| v.floor() as i32);
+        let step = dir.map(|v| if v < 0.0 { -1 } else { 1 });
+        let t_delta = dir.map(|v| (1.0 / v).abs());
+        let mut t_max = Vector3::new(0, 1, 2)
+            .map(|i| ((voxel[i] + step[i].max(0)) as f32 - origin[i]) / dir[i]);
+        let mut t = 0.0;
+        let mut filter = [1.0; 3];
+        // the block the ray is inside of, it may start out in a see-through one
+        let mut medium = self.get(voxel);
+
+        while t <= PROBE_RAY_LENGTH {
+            let axis = if t_max.x <= t_max.y && t_max.x <= t_max.z {
+                0
+            } else if t_max.y <= t_max.z {
+                1
+            } else {
+                2
+            };
+            if let Some(through) = light.filters[medium as usize] {
+                tint(
+                    &mut filter,
+                    through.depth(t_max[axis].min(PROBE_RAY_LENGTH) - t),
+                );
+            }
+            voxel[axis] += step[axis];
+            t = t_max[axis];
+            t_max[axis] += t_delta[axis];
+
+            let id = self.get(voxel);
+            if id == medium || t > PROBE_RAY_LENGTH {
+                continue;
+            }
+            if !light.is_clear(id) {
+                let mut normal = Vector3::new(0.0, 0.0, 0.0);
+                normal[axis] = -step[axis] as f32;
+                return (Some((id, origin + dir * t, normal)), filter);
+            }
+            if let Some(through) = light.filters[id as usize] {
+                tint(&mut filter, through.surface());
+            }
+            medium = id;
+        }
+        (None, filter)
+    }
+}
+
+/// Evenly spread directions over the whole sphere, the same for every probe.
+fn sphere_directions() -> Vec<Vector3<f32>> {
+    let golden_angle = std::f32::consts::PI * (3.0 - 5f32.sqrt());
+    (0..PROBE_RAYS)
+        .map(|i| {
+            let y = 1.0 - (i as f32 + 0.5) / PROBE_RAYS as f32 * 2.0;
+            let r = (1.0 - y * y).sqrt();
+            let angle = golden_angle * i as f32;
+            Vector3::new(r * angle.cos(), y, r * angle.sin())
+        })
+        .collect()
+}
+
+/// A coarse grid of irradiance probes spread evenly over a chunk, each holding the
+/// sun light bounced towards it by the blocks around it. The raymarcher blends
+/// between them for the indirect light at every hit.
+///
+/// Where the device has compute passes `ProbeBaker` bakes the probes straight into
+/// the texture and the grid is left unbaked. With the WebGL2 limits they're baked
+/// here on the CPU, like `OccupancyMips`. Probes inside opaque blocks have nothing
+/// sensible to say and are stored with alpha 0, the colour is premultiplied so the
+/// shader can leave them out of the blend.
+#[derive(Debug, Clone)]
+pub struct ProbeGrid {
+    resolution: u32,
+    /// Premultiplied colour and coverage per probe, x-major.
+    data: Vec<[u8; 4]>,
+    baked: bool,
+}
+
+impl ProbeGrid {
+    /// An unlit grid of `resolution`³ probes, see `is_baked`.
+    pub fn new(resolution: u32) -> Self {
+        Self {
+            resolution,
+            data: vec![[0; 4]; resolution.pow(3) as usize],
+            baked: false,
+        }
+    }
+
+    pub fn resolution(&self) -> u32 {
+        self.resolution
+    }
+
+    /// False until the first `update` that covers the whole grid.
+    pub fn is_baked(&self) -> bool {
+        self.baked
+    }
+
+    pub fn bounds(&self) -> VoxelRegion {
+        VoxelRegion {
+            min: [0; 3],
+            max: [self.resolution; 3],
+        }
+    }
+
+    /// Premultiplied colour and coverage of a probe.
+    pub fn probe(&self, x: u32, y: u32, z: u32) -> [u8; 4] {
+        self.data[self.index(x, y, z)]
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        (x + y * self.resolution + z * self.resolution.pow(2)) as usize
+    }
+
+    /// Voxels between neighbouring probes.
+    fn spacing(&self, chunk_size: u32) -> f32 {
+        chunk_size as f32 / self.resolution as f32
+    }
+
+    /// The probes of a grid of `resolution`³ that can see the edited `region` of a
+    /// chunk, including through the shadow rays of what they hit.
+    fn probes_near(resolution: u32, chunk_size: u32, region: VoxelRegion) -> VoxelRegion {
+        let spacing = chunk_size as f32 / resolution as f32;
+        let reach = PROBE_RAY_LENGTH * 2.0;
+        let to_probe = |v: f32| (v / spacing - 0.5).clamp(0.0, resolution as f32);
+        VoxelRegion {
+            min: region.min.map(|v| to_probe(v as f32 - reach).ceil() as u32),
+            max: region
+                .max
+                .map(|v| (to_probe(v as f32 + reach).floor() as u32 + 1).min(resolution)),
+        }
+    }
+
+    /// Where an edit of `region` of a chunk shows up for the probes of the neighbour
+    /// along `side`, as a box in the neighbour's layer of voxels facing the chunk.
+    /// `None` if the edit is too far from that side for them to see it.
+    pub fn seen_across(size: u32, region: VoxelRegion, side: usize) -> Option<VoxelRegion> {
+        let offset = NEIGHBOURS[side];
+        let axis = offset.iter().position(|&v| v != 0).unwrap();
+        let reach = (PROBE_RAY_LENGTH * 2.0) as u32;
+        let (near, layer) = if offset[axis] > 0 {
+            (region.max[axis] + reach >= size, 0)
+        } else {
+            (region.min[axis] <= reach, size - 1)
+        };
+        near.then(|| {
+            let mut seen = region;
+            seen.min[axis] = layer;
+            seen.max[axis] = layer + 1;
+            seen
+        })
+    }
+
+    /// Re-lights the probes around the edited `region` of `chunk` and returns them.
+    /// Passing `chunk.bounds()` bakes the whole grid. Everything outside the chunk
+    /// counts as air.
+    pub fn update(
+        &mut self,
+        chunk: &VoxelChunk,
+        region: VoxelRegion,
+        light: &BounceLight,
+    ) -> VoxelRegion {
+        self.update_regions(chunk, &Neighbours::default(), &[region], light)[0]
+    }
+
+    /// `update` for several edited regions at once, unpacking the chunk only once,
+    /// with the rays that leave the chunk going on through `neighbours`.
+    pub fn update_regions(
+        &mut self,
+        chunk: &VoxelChunk,
+        neighbours: &Neighbours,
+        regions: &[VoxelRegion],
+        light: &BounceLight,
+    ) -> Vec<VoxelRegion> {
+        let voxels = Voxels {
+            size: chunk.size(),
+            data: chunk.to_bytes(),
+            neighbours,
+        };
+        let directions = sphere_directions();
+        regions
+            .iter()
+            .map(|&region| self.relight(&voxels, region, light, &directions))
+            .collect()
+    }
+
+    fn relight(
+        &mut self,
+        voxels: &Voxels,
+        region: VoxelRegion,
+        light: &BounceLight,
+        directions: &[Vector3<f32>],
+    ) -> VoxelRegion {
+        let probes = Self::probes_near(self.resolution, voxels.size, region);
+        let spacing = self.spacing(voxels.size);
+
+        for z in probes.min[2]..probes.max[2] {
+            for y in probes.min[1]..probes.max[1] {
+                for x in probes.min[0]..probes.max[0] {
+                    let pos = Vector3::new(x, y, z).map(|v| (v as f32 + 0.5) * spacing);
+                    let texel = if !light.is_clear(voxels.get(pos.map(|v| v.floor() as i32))) {
+                        [0; 4]
+                    } else {
+                        let mut sum = [0.0; 3];
+                        for &dir in directions {
+                            let (hit, filter) = voxels.march(pos, dir, light);
+                            if let Some((id, hit, normal)) = hit {
+                                let mut radiance = light.radiance(voxels, id, hit, normal);
+                                tint(&mut radiance, filter);
+                                (0..3).for_each(|i| sum[i] += radiance[i]);
+                            }
+                        }
+                        let [r, g, b] = sum.map(|v| {
+                            (v / PROBE_RAYS as f32 * 255.0).round().clamp(0.0, 255.0) as u8
+                        });
+                        [r, g, b, 255]
+                    };
+                    let i = self.index(x, y, z);
+                    self.data[i] = texel;
+                }
+            }
+        }
+
+        if probes == self.bounds() {
+            self.baked = true;
+        }
+        probes
+    }
+
+    pub fn create_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let size = wgpu::Extent3d {
+            width: self.resolution,
+            height: self.resolution,
+            depth_or_array_layers: self.resolution,
+        };
+        let texture = Texture::create_filtered_volume(device, size, "probe_texture");
+        self.upload(queue, &texture, self.bounds());
+        texture
+    }
+
+    /// Copies a box of probes into `texture`.
+    pub fn upload(&self, queue: &wgpu::Queue, texture: &Texture, probes: VoxelRegion) {
+        if probes.is_empty() {
+            return;
+        }
+        if probes == self.bounds() {
+            texture.write_texels(queue, probes, self.data.as_flattened(), 4);
+            return;
+        }
+        let mut data = vec![];
+        for z in probes.min[2]..probes.max[2] {
+            for y in probes.min[1]..probes.max[1] {
+                let start = self.index(probes.min[0], y, z);
+                let end = start + probes.extent().width as usize;
+                data.extend(self.data[start..end].iter().flatten());
+            }
+        }
+        texture.write_texels(queue, probes, &data, 4);
+    }
+}
+
+/// Matches `Block` in `probes.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BlockRaw {
+    albedo: [f32; 3],
+    transparency: f32,
+    emissive: [f32; 3],
+    translucent: u32,
+    color: [f32; 3],
+    _padding: f32,
+}
+
+/// Matches `Light` in `probes.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BounceLightRaw {
+    sun_direction: [f32; 3],
+    _padding: f32,
+    sun_color: [f32; 3],
+    _padding2: f32,
+    blocks: [BlockRaw; MAX_BLOCKS],
+}
+
+impl BounceLight {
+    fn to_raw(&self) -> BounceLightRaw {
+        BounceLightRaw {
+            sun_direction: self.sun_direction.into(),
+            _padding: 0.0,
+            sun_color: self.sun_color,
+            _padding2: 0.0,
+            blocks: std::array::from_fn(|id| {
+                let (albedo, emissive) = self.materials[id];
+                let filter = self.filters[id].unwrap_or(Filter {
+                    transparency: 0.0,
+                    color: [1.0; 3],
+                    translucent: false,
+                });
+                BlockRaw {
+                    albedo,
+                    transparency: filter.transparency,
+                    emissive,
+                    translucent: filter.translucent as u32,
+                    color: filter.color,
+                    _padding: 0.0,
+                }
+            }),
+        }
+    }
+}
+
+/// Matches `Bake` in `probes.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct BakeRaw {
+    first: [u32; 3],
+    resolution: u32,
+    count: [u32; 3],
+    row_length: u32,
+}
+
+/// Probes along each axis one workgroup of `probes.wgsl` bakes.
+const BAKE_WORKGROUP: u32 = 4;
+
+/// Bakes `ProbeGrid`s in a compute pass, the same way `ProbeGrid::update_regions`
+/// does on the CPU, and copies them into their textures. The pass writes a buffer
+/// rather than the texture, GL only binds the first layer of 3D storage textures.
+pub struct ProbeBaker {
+    pipeline: wgpu::ComputePipeline,
+    bind_group_layout: wgpu::BindGroupLayout,
+    light_buffer: wgpu::Buffer,
+}
+
+impl ProbeBaker {
+    /// `None` on devices without compute passes or storage buffers, which are left
+    /// to bake on the CPU.
+    pub fn new(device: &wgpu::Device) -> Option<Self> {
+        let limits = device.limits();
+        if limits.max_compute_workgroups_per_dimension == 0
+            || limits.max_storage_buffers_per_shader_stage == 0
+        {
+            return None;
+        }
+
+        let volume = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D3,
+                sample_type: wgpu::TextureSampleType::Uint,
+            },
+            count: None,
+        };
+        let uniform = |binding| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::COMPUTE,
+            ty: wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                volume(0),
+                // the voxels of the chunks around, in `NEIGHBOURS` order
+                volume(1),
+                volume(2),
+                volume(3),
+                volume(4),
+                volume(5),
+                volume(6),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 7,
+                    visibility: wgpu::ShaderStages::COMPUTE,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Storage { read_only: false },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                uniform(8),
+                uniform(9),
+            ],
+            label: Some("probe_bake_bind_group_layout"),
+        });
+
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("probes.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(include_str!("probes.wgsl").into()),
+        });
+        let layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Probe Bake Pipeline Layout"),
+            bind_group_layouts: &[&bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let pipeline = device.create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+            label: Some("Probe Bake Pipeline"),
+            layout: Some(&layout),
+            module: &shader,
+            entry_point: "bake_probes",
+        });
+        let light_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Probe Light Buffer"),
+            size: std::mem::size_of::<BounceLightRaw>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        Some(Self {
+            pipeline,
+            bind_group_layout,
+            light_buffer,
+        })
+    }
+
+    /// What the next `bake` lights the probes with.
+    pub fn set_light(&self, queue: &wgpu::Queue, light: &BounceLight) {
+        queue.write_buffer(&self.light_buffer, 0, bytemuck::bytes_of(&light.to_raw()));
+    }
+
+    /// Re-lights the probes in `probes` around the edited `regions` of the chunk
+    /// whose voxels are in `voxels`, with the rays that leave it going on through
+    /// `neighbours`, see `ProbeGrid::update_regions`. Returns the probes relit.
+    pub fn bake(
+        &self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        voxels: &Texture,
+        neighbours: [&Texture; 6],
+        probes: &Texture,
+        regions: &[VoxelRegion],
+    ) -> Vec<VoxelRegion> {
+        let chunk_size = voxels.texture.width();
+        let resolution = probes.texture.width();
+        let relit = regions
+            .iter()
+            .map(|&region| ProbeGrid::probes_near(resolution, chunk_size, region))
+            .filter(|probes| !probes.is_empty())
+            .collect::<Vec<_>>();
+        let bakes = relit
+            .iter()
+            .map(|relit| {
+                let extent = relit.extent();
+                let align = wgpu::COPY_BYTES_PER_ROW_ALIGNMENT / 4;
+                let bake = BakeRaw {
+                    first: relit.min,
+                    resolution,
+                    count: [extent.width, extent.height, extent.depth_or_array_layers],
+                    row_length: extent.width.div_ceil(align) * align,
+                };
+                let output = device.create_buffer(&wgpu::BufferDescriptor {
+                    label: Some("Probe Bake Output"),
+                    size: (bake.row_length * extent.height * extent.depth_or_array_layers * 4)
+                        as u64,
+                    usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+                    mapped_at_creation: false,
+                });
+                let buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                    label: Some("Probe Bake Buffer"),
+                    contents: bytemuck::bytes_of(&bake),
+                    usage: wgpu::BufferUsages::UNIFORM,
+                });
+                let mut entries = vec![wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&voxels.view),
+                }];
+                entries.extend(neighbours.iter().zip(1..).map(|(neighbour, binding)| {
+                    wgpu::BindGroupEntry {
+                        binding,
+                        resource: wgpu::BindingResource::TextureView(&neighbour.view),
+                    }
+                }));
+                entries.extend([
+                    wgpu::BindGroupEntry {
+                        binding: 7,
+                        resource: output.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 8,
+                        resource: self.light_buffer.as_entire_binding(),
+                    },
+                    wgpu::BindGroupEntry {
+                        binding: 9,
+                        resource: buffer.as_entire_binding(),
+                    },
+                ]);
+                let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+                    layout: &self.bind_group_layout,
+                    entries: &entries,
+                    label: Some("probe_bake_bind_group"),
+                });
+                (bind_group, bake, output)
+            })
+            .collect::<Vec<_>>();
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Probe Bake Encoder"),
+        });
+        {
+            let mut pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+                label: Some("Probe Bake Pass"),
+                timestamp_writes: None,
+            });
+            pass.set_pipeline(&self.pipeline);
+            for (bind_group, bake, _) in &bakes {
+                let [x, y, z] = bake.count.map(|v| v.div_ceil(BAKE_WORKGROUP));
+                pass.set_bind_group(0, bind_group, &[]);
+                pass.dispatch_workgroups(x, y, z);
+            }
+        }
+        for ((_, bake, output), relit) in bakes.iter().zip(&relit) {
+            encoder.copy_buffer_to_texture(
+                wgpu::ImageCopyBuffer {
+                    buffer: output,
+                    layout: wgpu::ImageDataLayout {
+                        offset: 0,
+                        bytes_per_row: Some(bake.row_length * 4),
+                        rows_per_image: Some(bake.count[1]),
+                    },
+                },
+                wgpu::ImageCopyTexture {
+                    texture: &probes.texture,
+                    mip_level: 0,
+                    origin: relit.origin(),
+                    aspect: wgpu::TextureAspect::All,
+                },
+                relit.extent(),
+            );
+        }
+        queue.submit(std::iter::once(encoder.finish()));
+        relit
+    }
+}
@@ -1,31 +1,79 @@
 use std::f32;
 use wgpu::util::DeviceExt;
-use winit::event::{Event, WindowEvent};
+use winit::{
+    event::{ElementState, Event, KeyEvent, WindowEvent},
+    keyboard::{KeyCode, PhysicalKey},
+};
+pub mod blocks;
 pub mod camera;
-mod chunk_gen;
+pub mod chunk_gen;
 pub mod display_handler;
 pub mod instances;
+pub mod interaction;
+pub mod irradiance;
+pub mod light_levels;
+pub mod lighting;
+pub mod occupancy;
+pub mod raycast;
+pub mod save;
+pub mod streaming;
 pub mod texture;
+pub mod voxel;
+pub mod world;
+use blocks::BlockRegistry;
 use camera::Camera;
 use cgmath::prelude::*;
 use instances::*;
+use lighting::{Lighting, PointLights, MAX_POINT_LIGHTS};
 use texture::*;
+use world::World;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 struct CameraUniform {
     view_proj: [[f32; 4]; 4],
+    inv_view_proj: [[f32; 4]; 4],
 }
 
 impl CameraUniform {
     fn new() -> Self {
         Self {
             view_proj: cgmath::Matrix4::identity().into(),
+            inv_view_proj: cgmath::Matrix4::identity().into(),
         }
     }
 
     fn update_view_proj(&mut self, camera: &Camera) {
-        self.view_proj = camera.build_view_projection_matrix().into();
+        let view_proj = camera.build_view_projection_matrix();
+        self.view_proj = view_proj.into();
+        self.inv_view_proj = view_proj
+            .invert()
+            .unwrap_or_else(cgmath::Matrix4::identity)
+            .into();
+    }
+}
+
+/// Matches `Uniforms` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct UniformsRaw {
+    cam_pos: [f32; 3],
+    skip_empty_space: u32,
+}
+
+/// Switches for the raymarcher that can change between frames.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    /// Step over empty space using each chunk's `OccupancyMips`, off falls back to
+    /// the plain voxel DDA.
+    pub skip_empty_space: bool,
+}
+
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            skip_empty_space: true,
+        }
     }
 }
 
@@ -34,65 +82,157 @@ pub struct Storrage {
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     camera_buffer: wgpu::Buffer,
-    instance_buffer: wgpu::Buffer,
     vertex_list: Vec<Vertex>,
     indecies: Vec<u16>,
-    instances: Vec<instances::CFrame>,
-    diffuse_bind_group: wgpu::BindGroup,
+    uniform_bind_group: wgpu::BindGroup,
+    palette_buffer: wgpu::Buffer,
+    lighting_buffer: wgpu::Buffer,
+    point_light_buffer: wgpu::Buffer,
+    /// Lights `point_light_buffer` has room for.
+    point_light_room: usize,
+    /// Whether the point lights are in a storage buffer, which grows with them.
+    point_light_storage: bool,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
     depth_texture: Texture,
 }
 
 impl Storrage {
-    fn update_instance_buffer(&mut self, queue: &wgpu::Queue) {
-        let instance_data = self
-            .instances
-            .iter()
-            .map(|v| v.to_raw())
-            .collect::<Vec<_>>();
+    fn create_uniform_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        [uniforms, palette, lighting, point_lights]: [&wgpu::Buffer; 4],
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
+                        buffer: uniforms,
+                        offset: 0,
+                        size: None,
+                    }),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: palette.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: lighting.as_entire_binding(),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: point_lights.as_entire_binding(),
+                },
+            ],
+            label: Some("uniform_bind_group"),
+        })
+    }
 
-        queue.write_buffer(
-            &self.instance_buffer,
-            0,
-            bytemuck::cast_slice(&instance_data),
+    fn create_point_light_buffer(
+        device: &wgpu::Device,
+        room: usize,
+        storage: bool,
+    ) -> wgpu::Buffer {
+        let usage = if storage {
+            wgpu::BufferUsages::STORAGE
+        } else {
+            wgpu::BufferUsages::UNIFORM
+        };
+        device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Point Light Buffer"),
+            size: PointLights::buffer_size(room),
+            usage: usage | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    /// Makes room for `count` point lights, doubling the storage buffer until they
+    /// fit. A uniform buffer stays as it is, `PointLights` keeps to its limit.
+    fn fit_point_lights(&mut self, device: &wgpu::Device, count: usize) {
+        if !self.point_light_storage || count <= self.point_light_room {
+            return;
+        }
+        while self.point_light_room < count {
+            self.point_light_room *= 2;
+        }
+        self.point_light_buffer =
+            Self::create_point_light_buffer(device, self.point_light_room, true);
+        self.uniform_bind_group = Self::create_uniform_bind_group(
+            device,
+            &self.uniform_bind_group_layout,
+            [
+                &self.uniform_buffer,
+                &self.palette_buffer,
+                &self.lighting_buffer,
+                &self.point_light_buffer,
+            ],
         );
     }
 }
 
+/// `shader.wgsl` reads the point lights from a storage buffer. Devices without any
+/// get them in a uniform buffer with room for `MAX_POINT_LIGHTS` instead.
+fn shader_source(storage_buffers: bool) -> String {
+    let source = include_str!("shader.wgsl");
+    if storage_buffers {
+        return source.to_owned();
+    }
+    source
+        .replace(
+            "lights: array<PointLight>,",
+            &format!("lights: array<PointLight, {MAX_POINT_LIGHTS}>,"),
+        )
+        .replace(
+            "var<storage, read> point_lights",
+            "var<uniform> point_lights",
+        )
+}
+
 struct RenderScene<'a> {
     render_pipeline: &'a wgpu::RenderPipeline,
+    sky_pipeline: &'a wgpu::RenderPipeline,
     camera_bind_group: &'a wgpu::BindGroup,
     camera_uniform: CameraUniform,
     camera: &'a Camera,
     queue: &'a wgpu::Queue,
     device: &'a wgpu::Device,
-    surface: &'a wgpu::Surface,
-    window: &'a winit::window::Window,
+    view: &'a wgpu::TextureView,
     buffers: &'a Storrage,
+    world: &'a World,
+    settings: RenderSettings,
+    lighting: Lighting,
+    point_lights: &'a PointLights,
 }
 
 fn render_scene(scene: &mut RenderScene) {
     scene.queue.write_buffer(
         &scene.buffers.uniform_buffer,
         0,
-        &bytemuck::cast_slice(&[scene.camera.eye.x, scene.camera.eye.y, scene.camera.eye.z]),
+        bytemuck::cast_slice(&[UniformsRaw {
+            cam_pos: scene.camera.eye.into(),
+            skip_empty_space: scene.settings.skip_empty_space as u32,
+        }]),
+    );
+    scene.queue.write_buffer(
+        &scene.buffers.lighting_buffer,
+        0,
+        bytemuck::cast_slice(&[scene.lighting.to_raw()]),
+    );
+    scene.queue.write_buffer(
+        &scene.buffers.point_light_buffer,
+        0,
+        &scene.point_lights.to_raw(scene.buffers.point_light_room),
     );
 
-    scene.camera_uniform.update_view_proj(&scene.camera);
+    scene.camera_uniform.update_view_proj(scene.camera);
     scene.queue.write_buffer(
         &scene.buffers.camera_buffer,
         0,
         bytemuck::cast_slice(&[scene.camera_uniform]),
     );
 
-    let frame = scene
-        .surface
-        .get_current_texture()
-        .expect("Failed to get Current texture");
-
-    let view = frame
-        .texture
-        .create_view(&wgpu::TextureViewDescriptor::default());
-
     let mut encoer = scene
         .device
         .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
@@ -100,7 +240,7 @@ fn render_scene(scene: &mut RenderScene) {
         let mut render_pass = encoer.begin_render_pass(&wgpu::RenderPassDescriptor {
             label: None,
             color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                view: &view,
+                view: scene.view,
                 resolve_target: None,
                 ops: wgpu::Operations {
                     load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
@@ -119,35 +259,365 @@ fn render_scene(scene: &mut RenderScene) {
             timestamp_writes: None,
             occlusion_query_set: None,
         });
-        render_pass.set_pipeline(&scene.render_pipeline);
-        render_pass.set_vertex_buffer(0, scene.buffers.vertex_buffer.slice(..));
-        render_pass.set_vertex_buffer(1, scene.buffers.instance_buffer.slice(..));
         render_pass.set_bind_group(0, scene.camera_bind_group, &[]);
-        render_pass.set_bind_group(1, &scene.buffers.diffuse_bind_group, &[]);
+        render_pass.set_bind_group(1, &scene.buffers.uniform_bind_group, &[]);
+        render_pass.set_pipeline(scene.sky_pipeline);
+        render_pass.draw(0..3, 0..1);
+
+        render_pass.set_pipeline(scene.render_pipeline);
+        render_pass.set_vertex_buffer(0, scene.buffers.vertex_buffer.slice(..));
         render_pass.set_index_buffer(
             scene.buffers.index_buffer.slice(..),
             wgpu::IndexFormat::Uint16,
         );
-        render_pass.draw_indexed(
-            0..scene.buffers.indecies.len() as u32,
-            0,
-            0..scene.buffers.instances.len() as _,
+        scene.world.draw(
+            &mut render_pass,
+            scene.buffers.indecies.len() as u32,
+            scene.camera.eye,
         );
     }
     scene.queue.submit(Some(encoer.finish()));
-    frame.present();
-    scene.window.request_redraw();
 }
 
-pub async fn run(game_window: display_handler::GameWindow) {
+/// Everything `render_scene` needs that doesn't depend on where the frame ends up,
+/// so the same pipeline can draw into a window surface or an offscreen texture.
+pub struct Scene {
+    render_pipeline: wgpu::RenderPipeline,
+    sky_pipeline: wgpu::RenderPipeline,
+    camera_bind_group: wgpu::BindGroup,
+    camera_uniform: CameraUniform,
+    buffers: Storrage,
+    pub world: World,
+    pub blocks: BlockRegistry,
+    pub settings: RenderSettings,
+    pub lighting: Lighting,
+    pub point_lights: PointLights,
+}
+
+impl Scene {
+    pub fn new(
+        device: &wgpu::Device,
+        config: &wgpu::SurfaceConfiguration,
+        generator: std::sync::Arc<dyn chunk_gen::TerrainGenerator>,
+    ) -> Self {
+        let storage_buffers = device.limits().max_storage_buffers_per_shader_stage > 0;
+        let shader = device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some("shader.wgsl"),
+            source: wgpu::ShaderSource::Wgsl(shader_source(storage_buffers).into()),
+        });
+
+        let mut camera_uniform = CameraUniform::new();
+        camera_uniform.update_view_proj(&Camera::default(config));
+        let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Camera Buffer"),
+            contents: bytemuck::cast_slice(&[camera_uniform]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Vertex Buffer"),
+            contents: bytemuck::cast_slice(&Vec::<u8>::new()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Index Buffer"),
+            contents: bytemuck::cast_slice(&Vec::<u8>::new()),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: None,
+            size: std::mem::size_of::<UniformsRaw>() as u64,
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let world = World::new(device, generator);
+        let blocks = BlockRegistry::default();
+
+        let palette_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Palette Buffer"),
+            contents: bytemuck::cast_slice(&blocks.to_raw()),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        let lighting = Lighting::default();
+        let lighting_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Lighting Buffer"),
+            contents: bytemuck::cast_slice(&[lighting.to_raw()]),
+            usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        });
+
+        // storage buffers start out with as much room as uniform ones and grow
+        let point_lights = PointLights::new((!storage_buffers).then_some(MAX_POINT_LIGHTS));
+        let point_light_buffer =
+            Storrage::create_point_light_buffer(device, MAX_POINT_LIGHTS, storage_buffers);
+
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                wgpu::BindGroupLayoutEntry {
+                    binding: 2,
+                    visibility: wgpu::ShaderStages::all(),
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 4,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+                wgpu::BindGroupLayoutEntry {
+                    binding: 5,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: if storage_buffers {
+                            wgpu::BufferBindingType::Storage { read_only: true }
+                        } else {
+                            wgpu::BufferBindingType::Uniform
+                        },
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                },
+            ],
+            label: Some("uniform_bind_group_layout"),
+        });
+        let uniform_bind_group = Storrage::create_uniform_bind_group(
+            device,
+            &bind_group_layout,
+            [
+                &uniform_buffer,
+                &palette_buffer,
+                &lighting_buffer,
+                &point_light_buffer,
+            ],
+        );
+
+        let camera_bind_group_layout =
+            device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                entries: &[wgpu::BindGroupLayoutEntry {
+                    binding: 0,
+                    visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Buffer {
+                        ty: wgpu::BufferBindingType::Uniform,
+                        has_dynamic_offset: false,
+                        min_binding_size: None,
+                    },
+                    count: None,
+                }],
+                label: Some("camera_bind_group_layout"),
+            });
+
+        let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &camera_bind_group_layout,
+            entries: &[wgpu::BindGroupEntry {
+                binding: 0,
+                resource: camera_buffer.as_entire_binding(),
+            }],
+            label: Some("camera_bind_group"),
+        });
+
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("pipeline_layout"),
+            bind_group_layouts: &[
+                &camera_bind_group_layout,
+                &bind_group_layout,
+                world.bind_group_layout(),
+            ],
+            push_constant_ranges: &[],
+        });
+
+        let depth_texture = texture::Texture::cretate_depth_texture(device, config);
+
+        let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Render Pipeline"),
+            layout: Some(&pipeline_layout),
+
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_main",
+                buffers: &[Vertex::desc(), InstanceRaw::desc()],
+            },
+
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_main",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+
+                    blend: Some(wgpu::BlendState {
+                        color: wgpu::BlendComponent {
+                            src_factor: wgpu::BlendFactor::SrcAlpha,
+                            dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
+                            operation: wgpu::BlendOperation::Add,
+                        },
+                        alpha: wgpu::BlendComponent::OVER,
+                    }),
+
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+
+            primitive: wgpu::PrimitiveState {
+                front_face: wgpu::FrontFace::Ccw,
+                cull_mode: Some(wgpu::Face::Back),
+                ..Default::default()
+            },
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: true,
+                // misses are drawn on the far plane, where the sky pass left the depth
+                depth_compare: wgpu::CompareFunction::LessEqual,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        // fills the background before any chunk is drawn, without touching the depth
+        let sky_pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("sky_pipeline_layout"),
+            bind_group_layouts: &[&camera_bind_group_layout, &bind_group_layout],
+            push_constant_ranges: &[],
+        });
+        let sky_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
+            label: Some("Sky Pipeline"),
+            layout: Some(&sky_pipeline_layout),
+            vertex: wgpu::VertexState {
+                module: &shader,
+                entry_point: "vs_sky",
+                buffers: &[],
+            },
+            fragment: Some(wgpu::FragmentState {
+                module: &shader,
+                entry_point: "fs_sky",
+                targets: &[Some(wgpu::ColorTargetState {
+                    format: config.format,
+                    blend: None,
+                    write_mask: wgpu::ColorWrites::ALL,
+                })],
+            }),
+            primitive: wgpu::PrimitiveState::default(),
+            depth_stencil: Some(wgpu::DepthStencilState {
+                format: texture::Texture::DEPTH_FORMAT,
+                depth_write_enabled: false,
+                depth_compare: wgpu::CompareFunction::Always,
+                stencil: wgpu::StencilState::default(),
+                bias: wgpu::DepthBiasState::default(),
+            }),
+            multisample: wgpu::MultisampleState::default(),
+            multiview: None,
+        });
+
+        let mut buffers = Storrage {
+            uniform_buffer,
+            vertex_buffer,
+            uniform_bind_group,
+            palette_buffer,
+            lighting_buffer,
+            point_light_buffer,
+            point_light_room: MAX_POINT_LIGHTS,
+            point_light_storage: storage_buffers,
+            uniform_bind_group_layout: bind_group_layout,
+            camera_buffer,
+            index_buffer,
+            vertex_list: vec![],
+            indecies: vec![],
+            depth_texture,
+        };
+
+        let test =
+            instances::Mesh::from_file_obj(include_str!("./../../assets/untitled.obj").to_string());
+
+        // the bounding cube every chunk is drawn with
+        test.load(&mut buffers, device);
+
+        Self {
+            render_pipeline,
+            sky_pipeline,
+            camera_bind_group,
+            camera_uniform,
+            buffers,
+            world,
+            blocks,
+            settings: RenderSettings::default(),
+            lighting,
+            point_lights,
+        }
+    }
+
+    /// Sends the current `blocks` palette to the GPU, call after editing materials.
+    pub fn upload_blocks(&self, queue: &wgpu::Queue) {
+        queue.write_buffer(
+            &self.buffers.palette_buffer,
+            0,
+            bytemuck::cast_slice(&self.blocks.to_raw()),
+        );
+    }
+
+    fn render(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        view: &wgpu::TextureView,
+        camera: &Camera,
+    ) {
+        self.buffers
+            .fit_point_lights(device, self.point_lights.len());
+        render_scene(&mut RenderScene {
+            render_pipeline: &self.render_pipeline,
+            sky_pipeline: &self.sky_pipeline,
+            camera_bind_group: &self.camera_bind_group,
+            camera_uniform: self.camera_uniform,
+            camera,
+            queue,
+            device,
+            view,
+            buffers: &self.buffers,
+            world: &self.world,
+            settings: self.settings,
+            lighting: self.lighting,
+            point_lights: &self.point_lights,
+        });
+    }
+}
+
+/// Written with F5 and read back with F9.
+const SAVE_PATH: &str = "world.sav";
+
+pub async fn run(
+    game_window: display_handler::GameWindow,
+    generator: std::sync::Arc<dyn chunk_gen::TerrainGenerator>,
+) {
     let device = &game_window.device;
     let adapter = &game_window.adapter;
     let surface = &game_window.surface;
     let size = game_window.window.inner_size();
 
-    let shader = device.create_shader_module(wgpu::include_wgsl!("shader.wgsl"));
-
-    let swapchain_capabilities = surface.get_capabilities(&adapter);
+    let swapchain_capabilities = surface.get_capabilities(adapter);
     let swapchain_format = swapchain_capabilities.formats[0];
 
     let mut config = wgpu::SurfaceConfiguration {
@@ -161,205 +631,25 @@ pub async fn run(game_window: display_handler::GameWindow) {
     };
 
     let mut cam = Camera::default(&config);
-
-    let mut camera_uniform = CameraUniform::new();
-    camera_uniform.update_view_proj(&cam);
-
-    let camera_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Camera Buffer"),
-        contents: bytemuck::cast_slice(&[camera_uniform]),
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-    });
-
-    let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Vertex Buffer"),
-        contents: bytemuck::cast_slice(&Vec::<u8>::new()),
-        usage: wgpu::BufferUsages::VERTEX,
-    });
-
-    let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Index Buffer"),
-        contents: bytemuck::cast_slice(&Vec::<u8>::new()),
-        usage: wgpu::BufferUsages::INDEX,
-    });
-
-    let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
-        label: Some("Instance Buffer"),
-        contents: bytemuck::cast_slice(&Vec::<u8>::new()),
-        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
-    });
-
-    let uniform_buffer = device.create_buffer(&wgpu::BufferDescriptor {
-        label: None,
-        size: std::mem::size_of::<f32>() as u64 * 3,
-        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-        mapped_at_creation: false,
-    });
-
-    let diffuse_texture = chunk_gen::generate_chunk(&device, &game_window.queue);
-
-    let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-        entries: &[
-            wgpu::BindGroupLayoutEntry {
-                binding: 1,
-                visibility: wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Texture {
-                    multisampled: false,
-                    view_dimension: wgpu::TextureViewDimension::D3,
-                    sample_type: wgpu::TextureSampleType::Uint,
-                },
-                count: None,
-            },
-            wgpu::BindGroupLayoutEntry {
-                binding: 2,
-                visibility: wgpu::ShaderStages::all(),
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            },
-        ],
-        label: Some("texture_bind_group_layout"),
-    });
-    let diffuse_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &bind_group_layout,
-        entries: &[
-            wgpu::BindGroupEntry {
-                binding: 1,
-                resource: wgpu::BindingResource::TextureView(&diffuse_texture.view),
-            },
-            wgpu::BindGroupEntry {
-                binding: 2,
-                resource: wgpu::BindingResource::Buffer(wgpu::BufferBinding {
-                    buffer: &uniform_buffer,
-                    offset: 0,
-                    size: None,
-                }),
-            },
-        ],
-        label: Some("diffuse_bind_group"),
-    });
-
-    let camera_bind_group_layout =
-        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
-            entries: &[wgpu::BindGroupLayoutEntry {
-                binding: 0,
-                visibility: wgpu::ShaderStages::VERTEX | wgpu::ShaderStages::FRAGMENT,
-                ty: wgpu::BindingType::Buffer {
-                    ty: wgpu::BufferBindingType::Uniform,
-                    has_dynamic_offset: false,
-                    min_binding_size: None,
-                },
-                count: None,
-            }],
-            label: Some("camera_bind_group_layout"),
-        });
-
-    let camera_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
-        layout: &camera_bind_group_layout,
-        entries: &[wgpu::BindGroupEntry {
-            binding: 0,
-            resource: camera_buffer.as_entire_binding(),
-        }],
-        label: Some("camera_bind_group"),
-    });
-
-    let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-        label: Some("pipeline_layout"),
-        bind_group_layouts: &[&camera_bind_group_layout, &bind_group_layout],
-        push_constant_ranges: &[],
-    });
-
-    let depth_texture = texture::Texture::cretate_depth_texture(device, &config);
-
-    let render_pipeline = device.create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-        label: Some("Render Pipeline"),
-        layout: Some(&pipeline_layout),
-
-        vertex: wgpu::VertexState {
-            module: &shader,
-            entry_point: "vs_main",
-            buffers: &[Vertex::desc(), InstanceRaw::desc()],
-        },
-
-        fragment: Some(wgpu::FragmentState {
-            module: &shader,
-            entry_point: "fs_main",
-            targets: &[Some(wgpu::ColorTargetState {
-                format: swapchain_format.into(),
-
-                blend: Some(wgpu::BlendState {
-                    color: wgpu::BlendComponent {
-                        src_factor: wgpu::BlendFactor::SrcAlpha,
-                        dst_factor: wgpu::BlendFactor::OneMinusSrcAlpha,
-                        operation: wgpu::BlendOperation::Add,
-                    },
-                    alpha: wgpu::BlendComponent::OVER,
-                }),
-
-                write_mask: wgpu::ColorWrites::ALL,
-            })],
-        }),
-
-        primitive: wgpu::PrimitiveState {
-            front_face: wgpu::FrontFace::Ccw,
-            cull_mode: Some(wgpu::Face::Back),
-            ..Default::default()
-        },
-        depth_stencil: Some(wgpu::DepthStencilState {
-            format: texture::Texture::DEPTH_FORMAT,
-            depth_write_enabled: true,
-            depth_compare: wgpu::CompareFunction::Less,
-            stencil: wgpu::StencilState::default(),
-            bias: wgpu::DepthBiasState::default(),
-        }),
-
-        multisample: wgpu::MultisampleState::default(),
-        multiview: None,
-    });
-
-    let mut buffers = Storrage {
-        uniform_buffer,
-        vertex_buffer,
-        diffuse_bind_group,
-        camera_buffer,
-        index_buffer,
-        instance_buffer,
-        vertex_list: vec![],
-        indecies: vec![],
-        instances: Vec::new(),
-        depth_texture,
-    };
-
-    let test =
-        instances::Mesh::from_file_obj(include_str!("./../../assets/untitled.obj").to_string());
-
-    test.load(&mut buffers, device);
-    buffers.update_instance_buffer(&game_window.queue);
-
-    //let mut test = instances::Mesh::from_file_obj(include_str!("./../../assets/untitled.obj").to_string());
-    //test.cframe.position = [0.0, 2.0, 0.0].into();
-    //test.cframe.rotation = cgmath::Quaternion::from_angle_y(Deg(10.0));
-
-    //test.load(&mut buffers, device);
-    //buffers.update_instance_buffer(&game_window.queue);
+    let mut scene = Scene::new(device, &config, generator);
+    let mut streamer = streaming::ChunkStreamer::new(2);
 
     let mut cam_controller = camera::CameraController::new(0.5);
+    let mut interaction = interaction::BlockInteraction::default();
 
-    surface.configure(&device, &config);
+    surface.configure(device, &config);
     game_window
         .event_loop
         .run(move |event, target| {
-            let _ = (&adapter, &shader, &pipeline_layout);
+            let _ = &adapter;
 
             if let Event::DeviceEvent {
                 device_id: _,
                 event,
             } = &event
             {
-                cam_controller.process_events(&event);
+                cam_controller.process_events(event);
+                interaction.process_events(event);
             }
 
             if let Event::WindowEvent {
@@ -367,8 +657,37 @@ pub async fn run(game_window: display_handler::GameWindow) {
                 event,
             } = event
             {
+                interaction.process_window_events(&event);
+
                 match event {
                     WindowEvent::CloseRequested => target.exit(),
+                    WindowEvent::KeyboardInput {
+                        event:
+                            KeyEvent {
+                                physical_key: PhysicalKey::Code(key),
+                                state: ElementState::Pressed,
+                                repeat: false,
+                                ..
+                            },
+                        ..
+                    } => {
+                        let (action, result) = match key {
+                            KeyCode::F5 => ("saved", scene.world.save(SAVE_PATH)),
+                            KeyCode::F9 => ("loaded", scene.world.load(SAVE_PATH)),
+                            _ => return,
+                        };
+                        // there's no console on windows, so the title is where it shows
+                        let status = match result {
+                            Ok(()) => format!("{action} {SAVE_PATH}"),
+                            Err(err) => {
+                                eprintln!("{SAVE_PATH}: {err}");
+                                format!("{SAVE_PATH} not {action}: {err}")
+                            }
+                        };
+                        game_window
+                            .window
+                            .set_title(&format!("{} - {status}", display_handler::WINDOW_TITLE));
+                    }
                     WindowEvent::Resized(physical_size) => {
                         config.width = physical_size.width.max(1);
                         config.height = physical_size.height.max(1);
@@ -377,22 +696,23 @@ pub async fn run(game_window: display_handler::GameWindow) {
                     }
                     WindowEvent::RedrawRequested => {
                         cam_controller.update_camera(&mut cam);
+                        streamer.update(&mut scene.world, device, &game_window.queue, cam.eye);
+                        interaction.update(&mut scene.world, &cam);
+                        let light = irradiance::BounceLight::new(&scene.lighting, &scene.blocks);
+                        scene.world.upload_dirty(&game_window.queue, &light);
                         //test.cframe.position.y = 10.0;
                         //buffers.update_instance_buffer(&device);
 
-                        render_scene({
-                            &mut RenderScene {
-                                render_pipeline: &render_pipeline,
-                                camera_bind_group: &camera_bind_group,
-                                surface,
-                                device,
-                                window: &game_window.window,
-                                queue: &game_window.queue,
-                                camera_uniform,
-                                camera: &cam,
-                                buffers: &buffers,
-                            }
-                        });
+                        let frame = surface
+                            .get_current_texture()
+                            .expect("Failed to get Current texture");
+                        let view = frame
+                            .texture
+                            .create_view(&wgpu::TextureViewDescriptor::default());
+
+                        scene.render(device, &game_window.queue, &view, &cam);
+                        frame.present();
+                        game_window.window.request_redraw();
                     }
                     _ => {}
                 }
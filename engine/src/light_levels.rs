@@ -0,0 +1,331 @@
+use crate::{
+    blocks::{BlockRegistry, Material, MAX_BLOCKS},
+    texture::Texture,
+    voxel::{VoxelChunk, VoxelRegion},
+};
+use std::collections::VecDeque;
+
+/// Brightest a voxel gets, straight under the open sky or right next to a lamp.
+pub const MAX_LIGHT: u8 = 15;
+
+/// The six neighbours of a voxel, straight down first. Opposite ones come in pairs,
+/// so `side ^ 1` is across from `side`. Also the order of a chunk's sides in `Borders`.
+pub const NEIGHBOURS: [[i32; 3]; 6] = [
+    [0, -1, 0],
+    [0, 1, 0],
+    [-1, 0, 0],
+    [1, 0, 0],
+    [0, 0, -1],
+    [0, 0, 1],
+];
+
+/// What light levels need to know about every block id.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockLight {
+    /// Block light level the block gives off.
+    emission: Vec<u8>,
+    /// Whether light gets through, for air and see-through blocks.
+    clear: Vec<bool>,
+}
+
+impl BlockLight {
+    pub fn new(blocks: &BlockRegistry) -> Self {
+        let material = |id: usize| blocks.material(id as u8);
+        Self {
+            emission: (0..MAX_BLOCKS)
+                .map(|id| material(id).map_or(0, Material::light_level))
+                .collect(),
+            clear: (0..MAX_BLOCKS)
+                .map(|id| id == 0 || material(id).is_some_and(|m| m.transparency > 0.0))
+                .collect(),
+        }
+    }
+}
+
+/// What shines into a chunk from each side, in `NEIGHBOURS` order: the levels of the
+/// neighbour's layer of voxels along it, see `LightLevels::face`. `None` where there
+/// is no lit neighbour to take them from.
+pub type Borders = [Option<Vec<u8>>; 6];
+
+/// Minecraft style light levels for every voxel of a chunk, flood filled on the CPU.
+/// Sky light comes in from above at `MAX_LIGHT` and goes straight down without
+/// getting dimmer, block light comes from emissive blocks. Either one drops by a
+/// level for every step sideways, and only air and see-through blocks let it through.
+///
+/// The chunk is wrapped in a border one voxel thick holding the levels of the
+/// neighbouring chunks' voxels along each side, see `set_border`, so light flows on
+/// into the next chunk. Where no neighbour has said otherwise the border above is
+/// open sky and the others are dark. Levels are packed into a byte per voxel, sky in
+/// the high nibble and block light in the low one, and uploaded next to the voxels
+/// border and all.
+#[derive(Debug, Clone)]
+pub struct LightLevels {
+    size: u32,
+    /// `size + 2` voxels along each axis, the border included.
+    data: Vec<u8>,
+    built: bool,
+}
+
+impl LightLevels {
+    /// A chunk without any light under an open sky, see `is_built`.
+    pub fn new(size: u32) -> Self {
+        let mut levels = Self {
+            size,
+            data: vec![0; (size + 2).pow(3) as usize],
+            built: false,
+        };
+        // the border above is open sky until the chunk there says otherwise
+        for i in levels.layer(1, false).collect::<Vec<_>>() {
+            levels.data[i] = MAX_LIGHT << 4;
+        }
+        levels
+    }
+
+    /// False until the first `update` that covers the whole chunk.
+    pub fn is_built(&self) -> bool {
+        self.built
+    }
+
+    pub fn sky(&self, x: u32, y: u32, z: u32) -> u8 {
+        self.data[self.index(x, y, z)] >> 4
+    }
+
+    pub fn block(&self, x: u32, y: u32, z: u32) -> u8 {
+        self.data[self.index(x, y, z)] & 0xf
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        let padded = self.size + 2;
+        ((x + 1) + (y + 1) * padded + (z + 1) * padded.pow(2)) as usize
+    }
+
+    /// Index of a voxel of the chunk or its border.
+    fn padded_index(&self, voxel: [i32; 3]) -> Option<usize> {
+        voxel
+            .iter()
+            .all(|v| (-1..=self.size as i32).contains(v))
+            .then(|| {
+                let [x, y, z] = voxel.map(|v| (v + 1) as usize);
+                let padded = self.size as usize + 2;
+                x + y * padded + z * padded.pow(2)
+            })
+    }
+
+    /// Indices of the layer of voxels along `side`, the chunk's own outermost one or
+    /// the border just outside it. Both sides of an axis go in the same order, so a
+    /// chunk's layer lines up with its neighbour's border.
+    fn layer(&self, side: usize, inside: bool) -> impl Iterator<Item = usize> + '_ {
+        let offset = NEIGHBOURS[side];
+        let axis = offset.iter().position(|&v| v != 0).unwrap();
+        let along = match (offset[axis] > 0, inside) {
+            (false, false) => -1,
+            (false, true) => 0,
+            (true, true) => self.size as i32 - 1,
+            (true, false) => self.size as i32,
+        };
+        let size = self.size as i32;
+        (0..size).flat_map(move |b| {
+            (0..size).map(move |a| {
+                let mut voxel = [0; 3];
+                voxel[axis] = along;
+                voxel[(axis + 1) % 3] = a;
+                voxel[(axis + 2) % 3] = b;
+                self.padded_index(voxel).unwrap()
+            })
+        })
+    }
+
+    /// Levels of the chunk's layer of voxels along `side`, for the neighbour there
+    /// to `set_border` with.
+    pub fn face(&self, side: usize) -> Vec<u8> {
+        self.layer(side, true).map(|i| self.data[i]).collect()
+    }
+
+    /// Sets the border along `side` to `face`, the neighbour's levels next to it.
+    /// Returns whether that changed anything, the chunk then needs relighting for
+    /// the light to flow in.
+    pub fn set_border(&mut self, side: usize, face: &[u8]) -> bool {
+        assert_eq!(
+            face.len(),
+            self.size.pow(2) as usize,
+            "face of another size"
+        );
+        let indices = self.layer(side, false).collect::<Vec<_>>();
+        let mut changed = false;
+        for (i, &level) in indices.into_iter().zip(face) {
+            changed |= self.data[i] != level;
+            self.data[i] = level;
+        }
+        changed
+    }
+
+    /// `voxel` moved by `offset`, if that's still inside the chunk.
+    fn neighbour(&self, voxel: [i32; 3], [dx, dy, dz]: [i32; 3]) -> Option<[u32; 3]> {
+        let n = [voxel[0] + dx, voxel[1] + dy, voxel[2] + dz];
+        n.iter()
+            .all(|v| (0..self.size as i32).contains(v))
+            .then(|| n.map(|v| v as u32))
+    }
+
+    /// Everything an edit of `region` can change. Light spreads at most `MAX_LIGHT`
+    /// voxels from where it changed, except sky light which can fall all the way to
+    /// the bottom of the chunk.
+    fn reach(&self, region: VoxelRegion) -> VoxelRegion {
+        let reach = MAX_LIGHT as u32;
+        let mut area = VoxelRegion {
+            min: region.min.map(|v| v.saturating_sub(reach)),
+            max: region.max.map(|v| (v + reach).min(self.size)),
+        };
+        area.min[1] = 0;
+        area
+    }
+
+    /// Relights the voxels an edit of `region` in `chunk` can reach and returns them.
+    /// Passing `chunk.bounds()` lights the whole chunk.
+    ///
+    /// Nothing outside the reach of the edit changes, so the light there is kept and
+    /// floods back in from the border of the area, the same way the neighbouring
+    /// chunks' light floods in from the border around the chunk.
+    pub fn update(
+        &mut self,
+        chunk: &VoxelChunk,
+        region: VoxelRegion,
+        blocks: &BlockLight,
+    ) -> VoxelRegion {
+        self.update_regions(chunk, &[region], blocks)[0]
+    }
+
+    /// `update` for several edited regions at once, unpacking the chunk only once.
+    pub fn update_regions(
+        &mut self,
+        chunk: &VoxelChunk,
+        regions: &[VoxelRegion],
+        blocks: &BlockLight,
+    ) -> Vec<VoxelRegion> {
+        assert_eq!(chunk.size(), self.size, "chunk of another size");
+        let voxels = chunk.to_bytes();
+        regions
+            .iter()
+            .map(|&region| self.relight(&voxels, region, blocks))
+            .collect()
+    }
+
+    fn relight(&mut self, voxels: &[u8], region: VoxelRegion, blocks: &BlockLight) -> VoxelRegion {
+        let area = self.reach(region);
+        let id = |[x, y, z]: [u32; 3]| voxels[(x + y * self.size + z * self.size.pow(2)) as usize];
+        let mut queue = VecDeque::new();
+
+        for z in area.min[2]..area.max[2] {
+            for y in area.min[1]..area.max[1] {
+                for x in area.min[0]..area.max[0] {
+                    let i = self.index(x, y, z);
+                    self.data[i] = blocks.emission[id([x, y, z]) as usize].min(MAX_LIGHT);
+                    if self.data[i] != 0 {
+                        queue.push_back([x, y, z].map(|v| v as i32));
+                    }
+                }
+            }
+        }
+
+        // the lit voxels just outside the area shine back into it
+        for z in area.min[2]..area.max[2] {
+            for y in area.min[1]..area.max[1] {
+                for x in area.min[0]..area.max[0] {
+                    let on_border = (0..3).any(|i| {
+                        let v = [x, y, z][i];
+                        v == area.min[i] || v == area.max[i] - 1
+                    });
+                    if !on_border {
+                        continue;
+                    }
+                    for [dx, dy, dz] in NEIGHBOURS {
+                        let n = [x as i32 + dx, y as i32 + dy, z as i32 + dz];
+                        let outside =
+                            (0..3).any(|i| n[i] < area.min[i] as i32 || n[i] >= area.max[i] as i32);
+                        if outside && self.padded_index(n).is_some_and(|i| self.data[i] != 0) {
+                            queue.push_back(n);
+                        }
+                    }
+                }
+            }
+        }
+
+        while let Some(voxel) = queue.pop_front() {
+            let level = self.data[self.padded_index(voxel).unwrap()];
+            let (sky, block) = (level >> 4, level & 0xf);
+            for offset in NEIGHBOURS {
+                let Some(n @ [nx, ny, nz]) = self.neighbour(voxel, offset) else {
+                    continue;
+                };
+                if !blocks.clear[id(n) as usize] {
+                    continue;
+                }
+                let i = self.index(nx, ny, nz);
+                let down = offset == NEIGHBOURS[0];
+                let new_sky = if down && sky == MAX_LIGHT {
+                    MAX_LIGHT
+                } else {
+                    sky.saturating_sub(1)
+                };
+                let new_block = block.saturating_sub(1);
+                let (old_sky, old_block) = (self.data[i] >> 4, self.data[i] & 0xf);
+                if new_sky > old_sky || new_block > old_block {
+                    self.data[i] = new_sky.max(old_sky) << 4 | new_block.max(old_block);
+                    queue.push_back(n.map(|v| v as i32));
+                }
+            }
+        }
+
+        if area.min == [0; 3] && area.max == [self.size; 3] {
+            self.built = true;
+        }
+        area
+    }
+
+    pub fn create_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let padded = self.size + 2;
+        let size = wgpu::Extent3d {
+            width: padded,
+            height: padded,
+            depth_or_array_layers: padded,
+        };
+        let texture = Texture::create_voxel_texture(device, size, "light_texture");
+        self.upload(
+            queue,
+            &texture,
+            VoxelRegion {
+                min: [0; 3],
+                max: [self.size; 3],
+            },
+        );
+        texture
+    }
+
+    /// Copies the levels in `region` into `texture`, along with the voxels around it
+    /// so the border goes too.
+    pub fn upload(&self, queue: &wgpu::Queue, texture: &Texture, region: VoxelRegion) {
+        if region.is_empty() {
+            return;
+        }
+        // one voxel out on every side, which in the texture starts at 0
+        let padded = VoxelRegion {
+            min: region.min,
+            max: region.max.map(|v| v + 2),
+        };
+        // the whole chunk is the whole of `data`, no need to copy it out row by row
+        if region.min == [0; 3] && region.max == [self.size; 3] {
+            texture.write_voxels(queue, padded, &self.data);
+            return;
+        }
+        let [x0, y0, z0] = region.min.map(|v| v as i32 - 1);
+        let [x1, y1, z1] = region.max.map(|v| v as i32 + 1);
+        let mut data = vec![];
+        for z in z0..z1 {
+            for y in y0..y1 {
+                let start = self.padded_index([x0, y, z]).unwrap();
+                data.extend_from_slice(&self.data[start..start + (x1 - x0) as usize]);
+            }
+        }
+        texture.write_voxels(queue, padded, &data);
+    }
+}
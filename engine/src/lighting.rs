@@ -0,0 +1,241 @@
+use crate::world::CHUNK_WORLD_SIZE;
+use cgmath::{InnerSpace, Point3, Vector3};
+
+/// How ambient light is darkened in corners and crevices, from cheapest to best
+/// looking. The values are what the shader switches on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum AmbientOcclusion {
+    Off = 0,
+    /// Minecraft style, each corner of a face is darkened by the voxels touching it
+    /// and the result blended across the face.
+    #[default]
+    Vertex = 1,
+    /// A handful of short rays from every hit, soft but several marches per pixel.
+    Rays = 2,
+}
+
+/// Sun and sky light for the raymarcher, sent to the GPU every frame.
+#[derive(Debug, Clone, Copy)]
+pub struct Lighting {
+    /// Points from the scene towards the sun, doesn't need to be normalized.
+    pub sun_direction: Vector3<f32>,
+    pub sun_color: [f32; 3],
+    /// Light every face gets, whichever way it points.
+    pub ambient_color: [f32; 3],
+    /// Trace a ray towards the sun from every hit, on into the chunks sharing a
+    /// face with the one that was hit. The sky light level in front of the face
+    /// dims the sun as well, for whatever is overhead further away than that.
+    pub shadows: bool,
+    /// How far shadow rays go in world units, a chunk is `CHUNK_WORLD_SIZE` across.
+    pub shadow_distance: f32,
+    pub ambient_occlusion: AmbientOcclusion,
+    /// Dim the ambient light by each chunk's sky light level and add its block
+    /// light, see `LightLevels`.
+    pub light_levels: bool,
+    /// What full block light looks like, lamps and the like all glow the same.
+    pub block_light_color: [f32; 3],
+}
+
+impl Default for Lighting {
+    fn default() -> Self {
+        Self {
+            sun_direction: Vector3::new(0.4, 1.0, 0.3),
+            sun_color: [1.0, 0.95, 0.85],
+            ambient_color: [0.25, 0.28, 0.35],
+            shadows: true,
+            shadow_distance: CHUNK_WORLD_SIZE,
+            ambient_occlusion: AmbientOcclusion::default(),
+            light_levels: true,
+            block_light_color: [1.0, 0.8, 0.55],
+        }
+    }
+}
+
+impl Lighting {
+    pub fn to_raw(&self) -> LightingRaw {
+        LightingRaw {
+            sun_direction: self.sun_direction.normalize().into(),
+            shadows: self.shadows as u32,
+            sun_color: self.sun_color,
+            shadow_distance: self.shadow_distance,
+            ambient_color: self.ambient_color,
+            ambient_occlusion: self.ambient_occlusion as u32,
+            block_light_color: self.block_light_color,
+            light_levels: self.light_levels as u32,
+        }
+    }
+}
+
+/// Matches `Lighting` in `shader.wgsl`, the scalars fill the space after each `vec3`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct LightingRaw {
+    sun_direction: [f32; 3],
+    shadows: u32,
+    sun_color: [f32; 3],
+    shadow_distance: f32,
+    ambient_color: [f32; 3],
+    ambient_occlusion: u32,
+    block_light_color: [f32; 3],
+    light_levels: u32,
+}
+
+/// Point lights the shader can take at once on devices without storage buffers,
+/// where they go in a uniform buffer instead. Elsewhere there's no limit.
+pub const MAX_POINT_LIGHTS: usize = 64;
+
+/// Handle returned by `PointLights::add_point_light`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct LightId(u32);
+
+/// A coloured light that fades out to nothing at `radius`.
+#[derive(Debug, Clone, Copy)]
+pub struct PointLight {
+    pub position: Point3<f32>,
+    pub color: [f32; 3],
+    /// World units, nothing further away is lit.
+    pub radius: f32,
+    /// Trace a ray to the light from every hit it reaches. Like the sun's, they go on
+    /// into the chunks sharing a face with the one that was hit.
+    pub shadows: bool,
+}
+
+impl PointLight {
+    fn to_raw(self) -> PointLightRaw {
+        PointLightRaw {
+            position: self.position.into(),
+            radius: self.radius,
+            color: self.color,
+            shadows: self.shadows as u32,
+        }
+    }
+}
+
+/// The dynamic lights of a scene, uploaded every frame.
+#[derive(Debug, Clone)]
+pub struct PointLights {
+    lights: Vec<(LightId, PointLight)>,
+    next_id: u32,
+    limit: Option<usize>,
+}
+
+/// Room for `MAX_POINT_LIGHTS`, which every device takes.
+impl Default for PointLights {
+    fn default() -> Self {
+        Self::new(Some(MAX_POINT_LIGHTS))
+    }
+}
+
+impl PointLights {
+    /// No lights yet, with room for `limit` of them or as many as needed. `Scene::new`
+    /// picks whichever the device takes.
+    pub fn new(limit: Option<usize>) -> Self {
+        Self {
+            lights: vec![],
+            next_id: 0,
+            limit,
+        }
+    }
+
+    pub fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    /// Adds a light that casts shadows, change that through `get_mut`. Returns `None`
+    /// if there are already as many as the `limit`.
+    pub fn add_point_light(
+        &mut self,
+        position: Point3<f32>,
+        color: [f32; 3],
+        radius: f32,
+    ) -> Option<LightId> {
+        if self.limit.is_some_and(|limit| self.lights.len() >= limit) {
+            return None;
+        }
+        let id = LightId(self.next_id);
+        self.next_id += 1;
+        self.lights.push((
+            id,
+            PointLight {
+                position,
+                color,
+                radius,
+                shadows: true,
+            },
+        ));
+        Some(id)
+    }
+
+    /// Returns the light if it was still there.
+    pub fn remove(&mut self, id: LightId) -> Option<PointLight> {
+        let index = self.lights.iter().position(|(light, _)| *light == id)?;
+        Some(self.lights.remove(index).1)
+    }
+
+    pub fn get(&self, id: LightId) -> Option<&PointLight> {
+        self.lights
+            .iter()
+            .find(|(light, _)| *light == id)
+            .map(|(_, light)| light)
+    }
+
+    pub fn get_mut(&mut self, id: LightId) -> Option<&mut PointLight> {
+        self.lights
+            .iter_mut()
+            .find(|(light, _)| *light == id)
+            .map(|(_, light)| light)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (LightId, &PointLight)> {
+        self.lights.iter().map(|(id, light)| (*id, light))
+    }
+
+    pub fn len(&self) -> usize {
+        self.lights.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.lights.is_empty()
+    }
+
+    /// The first `room` lights the way the shader reads them, for the start of a
+    /// buffer of `buffer_size(room)`.
+    pub fn to_raw(&self, room: usize) -> Vec<u8> {
+        let lights = self.lights.iter().take(room);
+        let header = PointLightsHeader {
+            count: lights.len() as u32,
+            _padding: [0; 3],
+        };
+        let mut raw = bytemuck::bytes_of(&header).to_vec();
+        for (_, light) in lights {
+            raw.extend_from_slice(bytemuck::bytes_of(&light.to_raw()));
+        }
+        raw
+    }
+
+    /// Bytes a buffer with room for `room` lights takes, at least one since the
+    /// shader's array can't be empty.
+    pub fn buffer_size(room: usize) -> u64 {
+        (std::mem::size_of::<PointLightsHeader>()
+            + std::mem::size_of::<PointLightRaw>() * room.max(1)) as u64
+    }
+}
+
+/// Matches `PointLight` in `shader.wgsl`.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct PointLightRaw {
+    position: [f32; 3],
+    radius: f32,
+    color: [f32; 3],
+    shadows: u32,
+}
+
+/// Matches the start of `PointLights` in `shader.wgsl`, the array of `PointLightRaw`
+/// follows 16 bytes in.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
+struct PointLightsHeader {
+    count: u32,
+    _padding: [u32; 3],
+}
@@ -0,0 +1,192 @@
+use crate::{
+    texture::Texture,
+    voxel::{VoxelChunk, VoxelRegion},
+};
+
+/// Mip levels of a `size`³ voxel volume, halving down to a single texel.
+pub fn mip_level_count(size: u32) -> u32 {
+    u32::BITS - size.leading_zeros()
+}
+
+/// Where `mip` starts along z in the occupancy texture. Each level is at most half
+/// the one below so they never overlap, and the shader needs no running sum.
+pub fn level_offset(chunk_size: u32, mip: u32) -> u32 {
+    chunk_size - (chunk_size >> (mip - 1))
+}
+
+/// One coarse level, a flag per cell that is set if any of its 2³ children is solid.
+#[derive(Debug, Clone)]
+struct Level {
+    size: u32,
+    /// Where the level starts along z in the texture.
+    offset: u32,
+    data: Vec<u8>,
+}
+
+impl Level {
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        (x + y * self.size + z * self.size.pow(2)) as usize
+    }
+
+    fn is_solid(&self, x: u32, y: u32, z: u32) -> bool {
+        self.data[self.index(x, y, z)] != 0
+    }
+
+    fn bounds(&self) -> VoxelRegion {
+        VoxelRegion {
+            min: [0; 3],
+            max: [self.size; 3],
+        }
+    }
+
+    /// The cells covering a box of the level below, clipped to this level. Mip sizes
+    /// round down, so the last row of an odd-sized level has no parent at all.
+    fn cells_in(&self, region: VoxelRegion) -> VoxelRegion {
+        VoxelRegion {
+            min: region.min.map(|v| (v / 2).min(self.size)),
+            max: region.max.map(|v| v.div_ceil(2).min(self.size)),
+        }
+    }
+
+    /// Recomputes `cells` from the level below, given as a lookup by position.
+    fn refresh(&mut self, cells: VoxelRegion, child_solid: impl Fn(u32, u32, u32) -> bool) {
+        for z in cells.min[2]..cells.max[2] {
+            for y in cells.min[1]..cells.max[1] {
+                for x in cells.min[0]..cells.max[0] {
+                    let solid = (0..8).any(|i| {
+                        child_solid(x * 2 + (i & 1), y * 2 + (i >> 1 & 1), z * 2 + (i >> 2))
+                    });
+                    let i = self.index(x, y, z);
+                    self.data[i] = solid as u8;
+                }
+            }
+        }
+    }
+
+    /// The flags of `cells`, tightly packed for `Texture::write_voxels`.
+    fn texels_in(&self, cells: VoxelRegion) -> Vec<u8> {
+        let mut data = vec![];
+        for z in cells.min[2]..cells.max[2] {
+            for y in cells.min[1]..cells.max[1] {
+                let start = self.index(cells.min[0], y, z);
+                data.extend_from_slice(&self.data[start..start + cells.extent().width as usize]);
+            }
+        }
+        data
+    }
+
+    /// Where `cells` end up in the texture.
+    fn texture_region(&self, cells: VoxelRegion) -> VoxelRegion {
+        VoxelRegion {
+            min: [cells.min[0], cells.min[1], cells.min[2] + self.offset],
+            max: [cells.max[0], cells.max[1], cells.max[2] + self.offset],
+        }
+    }
+}
+
+/// The coarse mips of a chunk's voxels, level 0 being the block ids themselves. Each
+/// level flags the 2³ blocks of the one below that contain anything solid, so the
+/// raymarcher can step over whole empty cells at once.
+///
+/// The levels are stacked along z in a texture of their own, mip 1 first, instead of
+/// going into the mips of the voxel texture. The GL backend can't reliably
+/// `textureLoad` the upper mips of a 3D texture.
+#[derive(Debug, Clone)]
+pub struct OccupancyMips {
+    /// `levels[0]` is mip 1.
+    levels: Vec<Level>,
+}
+
+impl OccupancyMips {
+    pub fn build(chunk: &VoxelChunk) -> Self {
+        let mut levels: Vec<Level> = vec![];
+        for mip in 1..mip_level_count(chunk.size()) {
+            let size = chunk.size() >> mip;
+            levels.push(Level {
+                size,
+                offset: level_offset(chunk.size(), mip),
+                data: vec![0; size.pow(3) as usize],
+            });
+        }
+
+        // unpack once, a packed lookup per voxel would dominate the build
+        let voxels = chunk.to_bytes();
+        let chunk_size = chunk.size();
+        let mut below = None::<&Level>;
+        for level in &mut levels {
+            let bounds = level.bounds();
+            match below {
+                None => level.refresh(bounds, |x, y, z| {
+                    voxels[(x + y * chunk_size + z * chunk_size.pow(2)) as usize] != 0
+                }),
+                Some(below) => level.refresh(bounds, |x, y, z| below.is_solid(x, y, z)),
+            }
+            below = Some(level);
+        }
+
+        Self { levels }
+    }
+
+    /// Coarse levels, not counting the block ids in mip 0.
+    pub fn len(&self) -> usize {
+        self.levels.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.levels.is_empty()
+    }
+
+    /// Whether the cell at `mip` (1 or higher) has anything solid in it.
+    pub fn is_solid(&self, mip: usize, x: u32, y: u32, z: u32) -> bool {
+        self.levels[mip - 1].is_solid(x, y, z)
+    }
+
+    /// Re-checks the cells above the edited `region` of `chunk` on every level and
+    /// returns the cells that were looked at, one box per level from mip 1 up.
+    pub fn update(&mut self, chunk: &VoxelChunk, region: VoxelRegion) -> Vec<VoxelRegion> {
+        let mut touched = Vec::<VoxelRegion>::with_capacity(self.levels.len());
+        for i in 0..self.levels.len() {
+            let (below, level) = self.levels.split_at_mut(i);
+            let level = &mut level[0];
+            let cells = level.cells_in(touched.last().copied().unwrap_or(region));
+            match below.last() {
+                None => level.refresh(cells, |x, y, z| chunk.get(x, y, z) != 0),
+                Some(below) => level.refresh(cells, |x, y, z| below.is_solid(x, y, z)),
+            }
+            touched.push(cells);
+        }
+        touched
+    }
+
+    pub fn create_texture(&self, device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let width = self.levels.first().map_or(1, |level| level.size);
+        let depth = self
+            .levels
+            .last()
+            .map_or(1, |level| level.offset + level.size);
+        let size = wgpu::Extent3d {
+            width,
+            height: width,
+            depth_or_array_layers: depth,
+        };
+        let texture = Texture::create_voxel_texture(device, size, "occupancy_texture");
+        let cells = self.levels.iter().map(Level::bounds).collect::<Vec<_>>();
+        self.upload(queue, &texture, &cells);
+        texture
+    }
+
+    /// Copies the given boxes of every level into `texture`.
+    pub fn upload(&self, queue: &wgpu::Queue, texture: &Texture, cells: &[VoxelRegion]) {
+        for (level, cells) in self.levels.iter().zip(cells) {
+            if cells.is_empty() {
+                continue;
+            }
+            let region = level.texture_region(*cells);
+            if *cells == level.bounds() {
+                texture.write_voxels(queue, region, &level.data);
+            } else {
+                texture.write_voxels(queue, region, &level.texels_in(*cells));
+            }
+        }
+    }
+}
@@ -0,0 +1,208 @@
+// Bakes irradiance probes on the GPU, the same as `irradiance::ProbeGrid` does on
+// the CPU. See `irradiance::ProbeBaker`.
+
+/// Matches `irradiance::BlockRaw`, what a block does to the probes' light.
+struct Block {
+  albedo: vec3<f32>,
+  /// 0 for air and opaque blocks, see-through ones let light by with `color`.
+  transparency: f32,
+  emissive: vec3<f32>,
+  translucent: u32,
+  color: vec3<f32>,
+}
+
+/// Matches `irradiance::BounceLightRaw`.
+struct Light {
+  /// Towards the sun, normalized.
+  sun_direction: vec3<f32>,
+  sun_color: vec3<f32>,
+  blocks: array<Block, 256>,
+}
+
+/// Matches `irradiance::BakeRaw`, the box of probes one dispatch relights.
+struct Bake {
+  first: vec3<u32>,
+  resolution: u32,
+  count: vec3<u32>,
+  /// Texels from one row of `probes` to the next, padded for the copy into the
+  /// probe texture.
+  row_length: u32,
+}
+
+@group(0) @binding(0)
+var voxel_data: texture_3d<u32>;
+/// Voxels of the chunks sharing a face with this one, in `light_levels::NEIGHBOURS`
+/// order. Ones that aren't loaded are all air.
+@group(0) @binding(1)
+var neighbour_down: texture_3d<u32>;
+@group(0) @binding(2)
+var neighbour_up: texture_3d<u32>;
+@group(0) @binding(3)
+var neighbour_west: texture_3d<u32>;
+@group(0) @binding(4)
+var neighbour_east: texture_3d<u32>;
+@group(0) @binding(5)
+var neighbour_north: texture_3d<u32>;
+@group(0) @binding(6)
+var neighbour_south: texture_3d<u32>;
+/// The box of probes as packed `rgba8unorm` texels, copied into the probe texture
+/// afterwards.
+@group(0) @binding(7)
+var<storage, read_write> probes: array<u32>;
+@group(0) @binding(8)
+var<uniform> light: Light;
+@group(0) @binding(9)
+var<uniform> bake: Bake;
+
+/// Same as `PROBE_RAYS` and `PROBE_RAY_LENGTH` in `irradiance.rs`.
+const PROBE_RAYS: u32 = 32u;
+const PROBE_RAY_LENGTH: f32 = 16.0;
+/// Same as `TINT_DEPTH` in the shader.
+const TINT_DEPTH: f32 = 10.0;
+const PI: f32 = 3.14159265;
+
+/// Same as `world_voxel` in `shader.wgsl`.
+fn world_voxel(voxel: vec3<i32>, chunk_res: vec3<i32>) -> u32 {
+  let offset = select(vec3(0), vec3(-1), voxel < vec3(0))
+    + select(vec3(0), vec3(1), voxel >= chunk_res);
+  let local = voxel - offset * chunk_res;
+  if all(offset == vec3(0)) {
+    return textureLoad(voxel_data, local, 0).r;
+  }
+  if abs(offset.x) + abs(offset.y) + abs(offset.z) > 1 {
+    return 0u;
+  }
+  if offset.y < 0 {
+    return textureLoad(neighbour_down, local, 0).r;
+  } else if offset.y > 0 {
+    return textureLoad(neighbour_up, local, 0).r;
+  } else if offset.x < 0 {
+    return textureLoad(neighbour_west, local, 0).r;
+  } else if offset.x > 0 {
+    return textureLoad(neighbour_east, local, 0).r;
+  } else if offset.z < 0 {
+    return textureLoad(neighbour_north, local, 0).r;
+  }
+  return textureLoad(neighbour_south, local, 0).r;
+}
+
+/// Whether light gets through block `id`.
+fn is_clear(id: u32) -> bool {
+  return id == 0u || light.blocks[id].transparency > 0.0;
+}
+
+/// Light left after going in through the surface of see-through block `id`.
+fn surface(id: u32) -> vec3<f32> {
+  let block = light.blocks[id];
+  if block.translucent != 0u {
+    return vec3(block.transparency);
+  }
+  return block.color * block.transparency;
+}
+
+/// Light left after going `distance` voxels through see-through block `id`.
+fn depth(id: u32, distance: f32) -> vec3<f32> {
+  let block = light.blocks[id];
+  if block.translucent != 0u {
+    return pow(block.color, vec3(distance / TINT_DEPTH));
+  }
+  return vec3(1.0);
+}
+
+struct Hit {
+  /// The first opaque block, 0 if the ray got away.
+  id: u32,
+  pos: vec3<f32>,
+  normal: vec3<f32>,
+  /// What's left of light coming the other way past the see-through blocks.
+  tint: vec3<f32>,
+}
+
+/// `Voxels::march`, voxel by voxel from `origin` for up to `PROBE_RAY_LENGTH`.
+fn march(origin: vec3<f32>, direction: vec3<f32>, chunk_res: vec3<i32>) -> Hit {
+  let dir = select(direction, vec3(1e-6), abs(direction) < vec3(1e-6));
+  var voxel = vec3<i32>(floor(origin));
+  let step = select(vec3(1), vec3(-1), dir < vec3(0.0));
+  let t_delta = abs(1.0 / dir);
+  var t_max = (vec3<f32>(voxel + max(step, vec3(0))) - origin) / dir;
+  var t = 0.0;
+  var tint = vec3(1.0);
+  // the block the ray is inside of, it may start out in a see-through one
+  var medium = world_voxel(voxel, chunk_res);
+
+  while t <= PROBE_RAY_LENGTH {
+    var axis = 2;
+    if t_max.x <= t_max.y && t_max.x <= t_max.z {
+      axis = 0;
+    } else if t_max.y <= t_max.z {
+      axis = 1;
+    }
+    if light.blocks[medium].transparency > 0.0 {
+      tint *= depth(medium, min(t_max[axis], PROBE_RAY_LENGTH) - t);
+    }
+    voxel[axis] += step[axis];
+    t = t_max[axis];
+    t_max[axis] += t_delta[axis];
+
+    let id = world_voxel(voxel, chunk_res);
+    if id == medium || t > PROBE_RAY_LENGTH {
+      continue;
+    }
+    if !is_clear(id) {
+      var normal = vec3(0.0);
+      normal[axis] = -f32(step[axis]);
+      return Hit(id, origin + dir * t, normal, tint);
+    }
+    if light.blocks[id].transparency > 0.0 {
+      tint *= surface(id);
+    }
+    medium = id;
+  }
+  return Hit(0u, vec3(0.0), vec3(0.0), tint);
+}
+
+/// `BounceLight::radiance`, light leaving the face of block `id` at `pos`.
+fn radiance(id: u32, pos: vec3<f32>, normal: vec3<f32>, chunk_res: vec3<i32>) -> vec3<f32> {
+  let block = light.blocks[id];
+  let facing = max(dot(normal, light.sun_direction), 0.0);
+  var sun = vec3(facing);
+  if facing > 0.0 {
+    let shadow = march(pos + normal * 1e-3, light.sun_direction, chunk_res);
+    sun = select(shadow.tint * facing, vec3(0.0), shadow.id != 0u);
+  }
+  return block.emissive + block.albedo * light.sun_color * sun;
+}
+
+/// Direction `i` of `PROBE_RAYS` spread evenly over the sphere, `sphere_directions`.
+fn sphere_direction(i: u32) -> vec3<f32> {
+  let golden_angle = PI * (3.0 - sqrt(5.0));
+  let y = 1.0 - (f32(i) + 0.5) / f32(PROBE_RAYS) * 2.0;
+  let r = sqrt(1.0 - y * y);
+  let angle = golden_angle * f32(i);
+  return vec3(r * cos(angle), y, r * sin(angle));
+}
+
+@compute @workgroup_size(4, 4, 4)
+fn bake_probes(@builtin(global_invocation_id) id: vec3<u32>) {
+  if any(id >= bake.count) {
+    return;
+  }
+  let probe = bake.first + id;
+  let chunk_res = vec3<i32>(textureDimensions(voxel_data));
+  let spacing = f32(chunk_res.x) / f32(bake.resolution);
+  let pos = (vec3<f32>(probe) + 0.5) * spacing;
+
+  // probes stuck in opaque blocks are left out of the blend
+  var texel = vec4(0.0);
+  if is_clear(world_voxel(vec3<i32>(floor(pos)), chunk_res)) {
+    var sum = vec3(0.0);
+    for (var i = 0u; i < PROBE_RAYS; i++) {
+      let hit = march(pos, sphere_direction(i), chunk_res);
+      if hit.id != 0u {
+        sum += radiance(hit.id, hit.pos, hit.normal, chunk_res) * hit.tint;
+      }
+    }
+    texel = vec4(sum / f32(PROBE_RAYS), 1.0);
+  }
+  probes[id.x + (id.y + id.z * bake.count.y) * bake.row_length] = pack4x8unorm(texel);
+}
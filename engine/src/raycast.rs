@@ -0,0 +1,153 @@
+use crate::{
+    blocks::{self, BlockId},
+    voxel::VoxelChunk,
+    world::{self, VoxelCoord, World, VOXEL_SIZE},
+};
+use cgmath::{ElementWise, EuclideanSpace, InnerSpace, Point3, Vector3};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RayHit {
+    pub voxel: VoxelCoord,
+    pub block: BlockId,
+    /// Outward normal of the face the ray entered through, zero if it started inside.
+    pub normal: Vector3<i32>,
+    /// Distance from the ray origin in world units.
+    pub distance: f32,
+}
+
+impl RayHit {
+    /// The empty voxel in front of the hit face, where a placed block goes.
+    pub fn adjacent(&self) -> VoxelCoord {
+        self.voxel + self.normal
+    }
+}
+
+/// Steps voxel by voxel through the loaded chunks, the same DDA `RayCast` runs in
+/// the shader. Unloaded chunks count as air.
+pub fn raycast(
+    world: &World,
+    origin: Point3<f32>,
+    dir: Vector3<f32>,
+    max_distance: f32,
+) -> Option<RayHit> {
+    let dir = dir.normalize();
+    let pos = world::to_voxel_space(origin);
+    let max_t = max_distance / VOXEL_SIZE;
+
+    let mut voxel = pos.map(|v| v.floor() as i32);
+    let step = dir.map(|v| if v < 0.0 { -1 } else { 1 });
+    let t_delta = dir.map(|v| (1.0 / v).abs());
+    let mut t_max = Vector3::new(0, 1, 2).map(|i| {
+        if dir[i] == 0.0 {
+            f32::INFINITY
+        } else if dir[i] < 0.0 {
+            (pos[i] - voxel[i] as f32) * t_delta[i]
+        } else {
+            (voxel[i] as f32 + 1.0 - pos[i]) * t_delta[i]
+        }
+    });
+
+    let mut normal = Vector3::new(0, 0, 0);
+    let mut t = 0.0;
+
+    while t <= max_t {
+        match world.get_voxel(voxel) {
+            Some(block) if block != blocks::AIR => {
+                return Some(RayHit {
+                    voxel,
+                    block,
+                    normal,
+                    distance: t * VOXEL_SIZE,
+                })
+            }
+            _ => {}
+        }
+
+        // ties go to the lower axis, like `march_voxels` in the shader
+        let axis = if t_max.x <= t_max.y && t_max.x <= t_max.z {
+            0
+        } else if t_max.y <= t_max.z {
+            1
+        } else {
+            2
+        };
+
+        voxel[axis] += step[axis];
+        t = t_max[axis];
+        t_max[axis] += t_delta[axis];
+        normal = Vector3::new(0, 0, 0);
+        normal[axis] = -step[axis];
+    }
+
+    None
+}
+
+/// Slab test against the box `min..max`, the same as `intersect_box` in the shader.
+/// Returns how far along the ray it enters and leaves, the entry clamped to 0 if
+/// the ray starts inside, or `None` if it misses or the box is behind it.
+///
+/// Components of `dir` that are about 0 get nudged to a small positive value like
+/// the shader does, so a ray running along a face only counts as inside on the
+/// `min` side.
+pub fn intersect_box(
+    origin: Point3<f32>,
+    dir: Vector3<f32>,
+    min: Point3<f32>,
+    max: Point3<f32>,
+) -> Option<(f32, f32)> {
+    let inv_dir = safe_dir(dir).map(|v| 1.0 / v);
+    let t0 = (min - origin).mul_element_wise(inv_dir);
+    let t1 = (max - origin).mul_element_wise(inv_dir);
+    let t_near = Vector3::new(0, 1, 2).map(|i| t0[i].min(t1[i]));
+    let t_far = Vector3::new(0, 1, 2).map(|i| t0[i].max(t1[i]));
+
+    let enter = t_near.x.max(t_near.y).max(t_near.z).max(0.0);
+    let exit = t_far.x.min(t_far.y).min(t_far.z);
+    (enter <= exit).then_some((enter, exit))
+}
+
+fn safe_dir(dir: Vector3<f32>) -> Vector3<f32> {
+    dir.map(|v| if v.abs() < 1e-6 { 1e-6 } else { v })
+}
+
+/// What `fs_main` does for one pixel: `origin` and `dir` are in the chunk's -1..1
+/// cube, the march starts where the ray enters it and steps voxel by voxel like
+/// `march_voxels`. Returns the first solid voxel.
+pub fn march_chunk(chunk: &VoxelChunk, origin: Point3<f32>, dir: Vector3<f32>) -> Option<[u32; 3]> {
+    let (enter, _) = intersect_box(
+        origin,
+        dir,
+        Point3::new(-1.0, -1.0, -1.0),
+        Point3::new(1.0, 1.0, 1.0),
+    )?;
+    let size = chunk.size() as i32;
+    let dir = safe_dir(dir);
+    let pos = (origin + dir * enter)
+        .to_vec()
+        .map(|v| (v + 1.0) * size as f32 / 2.0);
+
+    let mut voxel = pos.map(|v| (v.floor() as i32).clamp(0, size - 1));
+    let step = dir.map(|v| v.signum() as i32);
+    let inv_dir = dir.map(|v| 1.0 / v);
+    let mut t_max =
+        Vector3::new(0, 1, 2).map(|i| ((voxel[i] + step[i].max(0)) as f32 - pos[i]) * inv_dir[i]);
+    let t_delta = inv_dir.map(f32::abs);
+
+    while (0..3).all(|i| (0..size).contains(&voxel[i])) {
+        let [x, y, z] = [voxel.x, voxel.y, voxel.z].map(|v| v as u32);
+        if chunk.get(x, y, z) != blocks::AIR {
+            return Some([x, y, z]);
+        }
+
+        let axis = if t_max.x <= t_max.y && t_max.x <= t_max.z {
+            0
+        } else if t_max.y <= t_max.z {
+            1
+        } else {
+            2
+        };
+        voxel[axis] += step[axis];
+        t_max[axis] += t_delta[axis];
+    }
+    None
+}
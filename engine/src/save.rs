@@ -0,0 +1,285 @@
+use crate::{
+    voxel::{PackedVoxels, VoxelChunk},
+    world::ChunkCoord,
+};
+use std::{
+    collections::HashSet,
+    io::{self, Read, Write},
+};
+
+/// First bytes of every save file.
+pub const MAGIC: [u8; 4] = *b"VXWD";
+/// Bumped whenever the layout below changes, older readers refuse newer files.
+///
+/// Layout, all integers little endian:
+/// - header: `MAGIC`, `u32` version, `u32` seed, `u16` length + generator id,
+///   `u32` chunk size, `u32` chunk count
+/// - per chunk: three `i32` coords, `u32` payload length, payload
+///
+/// Version 1 payloads are `encode_rle` of the raw block ids, later ones are
+/// `encode_packed`, the in-memory `PackedVoxels` as is. Before version 3 the
+/// generator id length was a `u8`, too short for `file:<dir>` ids of deep paths.
+pub const VERSION: u32 = 3;
+/// Largest chunk size a save may claim. A 256³ chunk is 16 MiB of block ids, so
+/// together with `max_payload_len` a corrupt header can't ask for more than a few
+/// tens of megabytes per chunk.
+pub const MAX_CHUNK_SIZE: u32 = 256;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SaveHeader {
+    pub version: u32,
+    pub seed: u32,
+    /// `TerrainGenerator::id` of the generator that made the untouched chunks.
+    pub generator: String,
+    pub chunk_size: u32,
+}
+
+pub struct SaveFile {
+    pub header: SaveHeader,
+    pub chunks: Vec<(ChunkCoord, VoxelChunk)>,
+}
+
+fn invalid(message: impl Into<String>) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.into())
+}
+
+/// Packs `data` as `(u16 run length, u8 block id)` triples.
+pub fn encode_rle(data: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    let mut rest = data;
+    while let Some(&id) = rest.first() {
+        let run = rest
+            .iter()
+            .take(u16::MAX as usize)
+            .take_while(|v| **v == id)
+            .count();
+        out.extend_from_slice(&(run as u16).to_le_bytes());
+        out.push(id);
+        rest = &rest[run..];
+    }
+    out
+}
+
+/// Inverse of `encode_rle`, fails unless the runs add up to exactly `len` voxels.
+pub fn decode_rle(payload: &[u8], len: usize) -> io::Result<Vec<u8>> {
+    if !payload.len().is_multiple_of(3) {
+        return Err(invalid("run-length payload is cut off"));
+    }
+
+    let mut data = Vec::with_capacity(len);
+    for run in payload.chunks_exact(3) {
+        let count = u16::from_le_bytes([run[0], run[1]]) as usize;
+        if count == 0 || data.len() + count > len {
+            return Err(invalid("run-length payload has the wrong voxel count"));
+        }
+        data.resize(data.len() + count, run[2]);
+    }
+
+    if data.len() != len {
+        return Err(invalid("run-length payload has the wrong voxel count"));
+    }
+    Ok(data)
+}
+
+/// Writes `voxels` as `u8` bits, `u8` palette length - 1, the palette, then the
+/// words as `(u16 run length, u64 word)` pairs since empty and solid stretches
+/// pack into long runs of identical words.
+pub fn encode_packed(voxels: &PackedVoxels) -> Vec<u8> {
+    let mut out = vec![voxels.bits() as u8, (voxels.palette().len() - 1) as u8];
+    out.extend_from_slice(voxels.palette());
+
+    let mut rest = voxels.words();
+    while let Some(&word) = rest.first() {
+        let run = rest
+            .iter()
+            .take(u16::MAX as usize)
+            .take_while(|w| **w == word)
+            .count();
+        out.extend_from_slice(&(run as u16).to_le_bytes());
+        out.extend_from_slice(&word.to_le_bytes());
+        rest = &rest[run..];
+    }
+    out
+}
+
+/// Inverse of `encode_packed` for `len` voxels.
+pub fn decode_packed(payload: &[u8], len: usize) -> io::Result<PackedVoxels> {
+    let [bits, palette_len, rest @ ..] = payload else {
+        return Err(invalid("packed payload is cut off"));
+    };
+    let palette_len = *palette_len as usize + 1;
+    if rest.len() < palette_len || !(rest.len() - palette_len).is_multiple_of(10) {
+        return Err(invalid("packed payload is cut off"));
+    }
+    let (palette, runs) = rest.split_at(palette_len);
+
+    let mut words = vec![];
+    for run in runs.chunks_exact(10) {
+        let count = u16::from_le_bytes([run[0], run[1]]) as usize;
+        let word = u64::from_le_bytes(run[2..].try_into().unwrap());
+        if count == 0 || words.len() + count > len {
+            return Err(invalid("packed payload has too many words"));
+        }
+        words.resize(words.len() + count, word);
+    }
+
+    PackedVoxels::from_parts(len, palette.to_vec(), *bits as u32, words).map_err(invalid)
+}
+
+/// Longest payload `version` can need for a `chunk_size`³ chunk: a run per voxel for
+/// `encode_rle`, a run per word of 8 bit indices and a full palette for
+/// `encode_packed`.
+pub fn max_payload_len(version: u32, chunk_size: u32) -> u64 {
+    let len = (chunk_size as u64).pow(3);
+    match version {
+        1 => 3 * len,
+        _ => 2 + 256 + 10 * len.div_ceil(8),
+    }
+}
+
+fn read_u32(reader: &mut impl Read) -> io::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+fn read_i32(reader: &mut impl Read) -> io::Result<i32> {
+    read_u32(reader).map(|v| v as i32)
+}
+
+pub fn write<'a>(
+    mut writer: impl Write,
+    header: &SaveHeader,
+    chunks: impl ExactSizeIterator<Item = (ChunkCoord, &'a VoxelChunk)>,
+) -> io::Result<()> {
+    if !(1..=VERSION).contains(&header.version) {
+        return Err(invalid(format!(
+            "can't write save version {}",
+            header.version
+        )));
+    }
+    let generator = header.generator.as_bytes();
+    let max_len = match header.version {
+        1 | 2 => u8::MAX as usize,
+        _ => u16::MAX as usize,
+    };
+    if generator.len() > max_len {
+        return Err(invalid("generator id is too long"));
+    }
+
+    writer.write_all(&MAGIC)?;
+    writer.write_all(&header.version.to_le_bytes())?;
+    writer.write_all(&header.seed.to_le_bytes())?;
+    match header.version {
+        1 | 2 => writer.write_all(&[generator.len() as u8])?,
+        _ => writer.write_all(&(generator.len() as u16).to_le_bytes())?,
+    }
+    writer.write_all(generator)?;
+    writer.write_all(&header.chunk_size.to_le_bytes())?;
+    writer.write_all(&(chunks.len() as u32).to_le_bytes())?;
+
+    for (coord, voxels) in chunks {
+        if voxels.size() != header.chunk_size {
+            return Err(invalid(format!(
+                "chunk {coord:?} has size {}, expected {}",
+                voxels.size(),
+                header.chunk_size
+            )));
+        }
+
+        let payload = match header.version {
+            1 => encode_rle(&voxels.to_bytes()),
+            _ => encode_packed(voxels.packed()),
+        };
+        for v in [coord.x, coord.y, coord.z] {
+            writer.write_all(&v.to_le_bytes())?;
+        }
+        writer.write_all(&(payload.len() as u32).to_le_bytes())?;
+        writer.write_all(&payload)?;
+    }
+    writer.flush()
+}
+
+pub fn read(mut reader: impl Read) -> io::Result<SaveFile> {
+    let mut magic = [0; 4];
+    reader.read_exact(&mut magic)?;
+    if magic != MAGIC {
+        return Err(invalid("not a world save"));
+    }
+
+    let version = read_u32(&mut reader)?;
+    if version == 0 || version > VERSION {
+        return Err(invalid(format!(
+            "save version {version} isn't supported, the newest is {VERSION}"
+        )));
+    }
+
+    let seed = read_u32(&mut reader)?;
+    let len = match version {
+        1 | 2 => {
+            let mut len = [0];
+            reader.read_exact(&mut len)?;
+            len[0] as usize
+        }
+        _ => {
+            let mut len = [0; 2];
+            reader.read_exact(&mut len)?;
+            u16::from_le_bytes(len) as usize
+        }
+    };
+    let mut generator = vec![0; len];
+    reader.read_exact(&mut generator)?;
+    let generator =
+        String::from_utf8(generator).map_err(|_| invalid("generator id isn't utf-8"))?;
+
+    let chunk_size = read_u32(&mut reader)?;
+    if !(1..=MAX_CHUNK_SIZE).contains(&chunk_size) {
+        return Err(invalid(format!("chunk size {chunk_size} is out of range")));
+    }
+    let header = SaveHeader {
+        version,
+        seed,
+        generator,
+        chunk_size,
+    };
+
+    let count = read_u32(&mut reader)?;
+    let mut chunks = vec![];
+    let mut seen = HashSet::new();
+    for _ in 0..count {
+        let coord = ChunkCoord::new(
+            read_i32(&mut reader)?,
+            read_i32(&mut reader)?,
+            read_i32(&mut reader)?,
+        );
+        if !seen.insert(coord) {
+            return Err(invalid(format!("chunk {coord:?} is saved twice")));
+        }
+        let payload_len = read_u32(&mut reader)? as u64;
+        if payload_len > max_payload_len(version, chunk_size) {
+            return Err(invalid(format!(
+                "chunk {coord:?} claims a {payload_len} byte payload"
+            )));
+        }
+        // grows with what's actually there instead of trusting the length up front
+        let mut payload = vec![];
+        (&mut reader).take(payload_len).read_to_end(&mut payload)?;
+        if payload.len() as u64 != payload_len {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("chunk {coord:?} is cut off"),
+            ));
+        }
+
+        let len = chunk_size.pow(3) as usize;
+        let mut voxels = match version {
+            1 => VoxelChunk::from_raw(chunk_size, decode_rle(&payload, len)?),
+            _ => VoxelChunk::from_packed(chunk_size, decode_packed(&payload, len)?),
+        };
+        // whatever ended up in a save differs from what the generator makes
+        voxels.set_modified(true);
+        chunks.push((coord, voxels));
+    }
+
+    Ok(SaveFile { header, chunks })
+}
@@ -1,5 +1,7 @@
 struct CameraUniform {
     view_proj: mat4x4<f32>,
+    /// Takes clip space back to the world, for the sky pass.
+    inv_view_proj: mat4x4<f32>,
 };
 @group(0) @binding(0)
 var<uniform> camera: CameraUniform;
@@ -60,171 +62,704 @@ struct Uniforms {
   cam_x : f32,
   cam_y : f32,
   cam_z : f32,
+  skip_empty_space : u32,
 }
 
-@group(1) @binding(1)
-var voxel_data: texture_3d<u32>;
-@group(1) @binding(2)
-var<uniform> uniforms: Uniforms;
+/// Matches `lighting::LightingRaw`.
+struct Lighting {
+  /// Towards the sun, normalized.
+  sun_direction: vec3<f32>,
+  shadows: u32,
+  sun_color: vec3<f32>,
+  /// In world units, the same as the -1..1 cube's.
+  shadow_distance: f32,
+  ambient_color: vec3<f32>,
+  /// 0 off, 1 per corner, 2 rays, see `lighting::AmbientOcclusion`.
+  ambient_occlusion: u32,
+  block_light_color: vec3<f32>,
+  light_levels: u32,
+}
 
+/// Matches `lighting::PointLightRaw`.
+struct PointLight {
+  /// World space.
+  position: vec3<f32>,
+  radius: f32,
+  color: vec3<f32>,
+  shadows: u32,
+}
 
-fn rayCubeIntersection(rayOrigin: vec3<f32>, rayDirection: vec3<f32>, cubeMin: vec3<f32>, cubeMax: vec3<f32>) -> vec3<f32> {
+/// Matches `lighting::PointLights::to_raw`, only the first `count` are in use. Without
+/// storage buffers it's a uniform with room for `MAX_POINT_LIGHTS`, see `shader_source`.
+struct PointLights {
+  count: u32,
+  lights: array<PointLight>,
+}
 
-    if (rayOrigin.x >= cubeMin.x && rayOrigin.x <= cubeMax.x &&
-        rayOrigin.y >= cubeMin.y && rayOrigin.y <= cubeMax.y &&
-        rayOrigin.z >= cubeMin.z && rayOrigin.z <= cubeMax.z) {
-        return rayOrigin;
-    }
+struct Material {
+  color: vec4<f32>,
+  emissive: vec4<f32>,
+  roughness: f32,
+  transparency: f32,
+  translucent: u32,
+}
 
-    var tmin: f32 = (cubeMin.x - rayOrigin.x) / rayDirection.x;
-    var tmax: f32 = (cubeMax.x - rayOrigin.x) / rayDirection.x;
+@group(1) @binding(2)
+var<uniform> uniforms: Uniforms;
+@group(1) @binding(3)
+var<uniform> palette: array<Material, 256>;
+@group(1) @binding(4)
+var<uniform> lighting: Lighting;
+@group(1) @binding(5)
+var<storage, read> point_lights: PointLights;
+@group(2) @binding(0)
+var voxel_data: texture_3d<u32>;
+/// Levels 1 and up of `voxel_data` stacked along z, level `n` being 1 for every 2^n
+/// sized cell with anything solid in it.
+@group(2) @binding(1)
+var occupancy: texture_3d<u32>;
+/// Irradiance probes spread evenly over the chunk, see `irradiance::ProbeGrid`.
+@group(2) @binding(2)
+var probes: texture_3d<f32>;
+@group(2) @binding(3)
+var probe_sampler: sampler;
+/// Sky light level in the high nibble and block light in the low one for every
+/// voxel and a border one voxel thick around them, see `light_levels::LightLevels`.
+@group(2) @binding(4)
+var light_levels: texture_3d<u32>;
+/// Voxels of the chunks sharing a face with this one, in `light_levels::NEIGHBOURS`
+/// order. Ones that aren't loaded are all air.
+@group(2) @binding(5)
+var neighbour_down: texture_3d<u32>;
+@group(2) @binding(6)
+var neighbour_up: texture_3d<u32>;
+@group(2) @binding(7)
+var neighbour_west: texture_3d<u32>;
+@group(2) @binding(8)
+var neighbour_east: texture_3d<u32>;
+@group(2) @binding(9)
+var neighbour_north: texture_3d<u32>;
+@group(2) @binding(10)
+var neighbour_south: texture_3d<u32>;
+
+
+struct Ray {
+  origin: vec3<f32>,
+  dir: vec3<f32>,
+  inv_dir: vec3<f32>,
+  step: vec3<i32>,
+  /// Marching gives up past this distance.
+  max_t: f32,
+}
 
-    if (tmin > tmax) {
-      let temp = tmin;
-        tmin = tmax;
-        tmax = temp;
-    }
+fn new_ray(origin: vec3<f32>, dir: vec3<f32>) -> Ray {
+  // axis-aligned rays would divide by zero, nudge them off the axis instead
+  let safe_dir = select(dir, vec3(1e-6), abs(dir) < vec3(1e-6));
+  var ray: Ray;
+  ray.origin = origin;
+  ray.dir = safe_dir;
+  ray.inv_dir = 1.0 / safe_dir;
+  ray.step = vec3<i32>(sign(safe_dir));
+  ray.max_t = 1e30;
+  return ray;
+}
+
+/// Slab test against the box `lo..hi`: how far along the ray it enters and leaves,
+/// the entry clamped to 0 if the ray starts inside. It's a miss if `x > y`, which
+/// covers boxes behind the ray too. Matches `raycast::intersect_box`.
+fn intersect_box(ray: Ray, lo: vec3<f32>, hi: vec3<f32>) -> vec2<f32> {
+  let t0 = (lo - ray.origin) * ray.inv_dir;
+  let t1 = (hi - ray.origin) * ray.inv_dir;
+  let t_near = min(t0, t1);
+  let t_far = max(t0, t1);
+  return vec2(
+    max(max(t_near.x, t_near.y), max(t_near.z, 0.0)),
+    min(t_far.x, min(t_far.y, t_far.z)),
+  );
+}
 
-    var tymin: f32 = (cubeMin.y - rayOrigin.y) / rayDirection.y;
-    var tymax: f32 = (cubeMax.y - rayOrigin.y) / rayDirection.y;
+/// The face of `lo..hi` the ray comes in through, zero if it starts inside.
+fn entry_normal(ray: Ray, lo: vec3<f32>, hi: vec3<f32>) -> vec3<f32> {
+  let t_near = min((lo - ray.origin) * ray.inv_dir, (hi - ray.origin) * ray.inv_dir);
+  if max(t_near.x, max(t_near.y, t_near.z)) <= 0.0 {
+    return vec3(0.0);
+  }
+  if t_near.x >= t_near.y && t_near.x >= t_near.z {
+    return vec3(-f32(ray.step.x), 0.0, 0.0);
+  } else if t_near.y >= t_near.z {
+    return vec3(0.0, -f32(ray.step.y), 0.0);
+  }
+  return vec3(0.0, 0.0, -f32(ray.step.z));
+}
 
-    if (tymin > tymax) {
-      let temp = tymin;
-        tymin = tymax;
-        tymax = temp;
-    }
+struct Hit {
+  /// Block id, 0 if the ray got through or came out of a see-through block into air.
+  id: u32,
+  /// How far along the ray it entered the block.
+  t: f32,
+  /// Face of the block the ray entered through, opposite to the last axis stepped.
+  normal: vec3<f32>,
+}
 
-    if (tmin > tymax || tymin > tmax) {
-        return vec3<f32>(0.0, 0.0, 0.0); // Intersection is nil
+/// Steps voxel by voxel through the box `lo..hi` starting `t` along the ray, returns
+/// the first block other than `medium`, the block the ray is inside of, or id 0 once
+/// the ray leaves the box or goes past `max_t`. `normal` is the face
+/// the ray came in through at `t`.
+fn march_voxels(ray: Ray, lo: vec3<i32>, hi: vec3<i32>, t: f32, normal: vec3<f32>, medium: u32) -> Hit {
+  var voxel = clamp(vec3<i32>(floor(ray.origin + ray.dir * t)), lo, hi - vec3(1));
+  var t_max = (vec3<f32>(voxel + max(ray.step, vec3(0))) - ray.origin) * ray.inv_dir;
+  let t_delta = abs(ray.inv_dir);
+  var t_enter = t;
+  var face = normal;
+
+  while all(voxel >= lo) && all(voxel < hi) && t_enter <= ray.max_t {
+    let id = textureLoad(voxel_data, voxel, 0).r;
+    if id != medium {
+      return Hit(id, t_enter, face);
     }
 
-    if (tymin > tmin) {
-        tmin = tymin;
+    if t_max.x <= t_max.y && t_max.x <= t_max.z {
+      voxel.x += ray.step.x;
+      t_enter = t_max.x;
+      t_max.x += t_delta.x;
+      face = vec3(-f32(ray.step.x), 0.0, 0.0);
+    } else if t_max.y <= t_max.z {
+      voxel.y += ray.step.y;
+      t_enter = t_max.y;
+      t_max.y += t_delta.y;
+      face = vec3(0.0, -f32(ray.step.y), 0.0);
+    } else {
+      voxel.z += ray.step.z;
+      t_enter = t_max.z;
+      t_max.z += t_delta.z;
+      face = vec3(0.0, 0.0, -f32(ray.step.z));
     }
+  }
+  return Hit(0u, t_enter, face);
+}
 
-    if (tymax < tmax) {
-        tmax = tymax;
-    }
+/// Upper bound on traversal steps, crossing a chunk takes far fewer.
+const MAX_STEPS: i32 = 4096;
 
-    var tzmin: f32 = (cubeMin.z - rayOrigin.z) / rayDirection.z;
-    var tzmax: f32 = (cubeMax.z - rayOrigin.z) / rayDirection.z;
+/// Where `level` starts along z in `occupancy`, matches `occupancy::level_offset`.
+fn level_offset(chunk_size: i32, level: i32) -> i32 {
+  return chunk_size - (chunk_size >> u32(level - 1));
+}
 
-    if (tzmin > tzmax) {
-      let temp = tzmin;
-        tzmin = tzmax;
-        tzmax = temp;
+/// Finest occupancy level `march_mips` descends to, solid cells there are marched
+/// voxel by voxel.
+const LEAF_LEVEL: i32 = 2;
+
+/// Walks the occupancy levels: empty cells are stepped over whole, solid ones are
+/// looked at again one level down. Every time the ray leaves the cell above as well
+/// it goes up a level, so long empty stretches are crossed in ever larger steps.
+fn march_mips(ray: Ray, chunk_res: vec3<i32>, normal: vec3<f32>) -> Hit {
+  // same as `mip_level_count` - 1
+  var top = 0;
+  while (chunk_res.x >> u32(top + 1)) > 0 {
+    top += 1;
+  }
+  var level = LEAF_LEVEL;
+  var voxel = clamp(vec3<i32>(floor(ray.origin)), vec3(0), chunk_res - vec3(1));
+  // distance at which the ray entered the current cell
+  var t = 0.0;
+  var face = normal;
+
+  for (var i = 0; i < MAX_STEPS; i++) {
+    if any(voxel < vec3(0)) || any(voxel >= chunk_res) || t > ray.max_t {
+      break;
     }
 
-    if (tmin > tzmax || tzmin > tmax) {
-        return vec3(100000.0); 
+    // go down until the cell is empty or small enough to march through
+    var cell: vec3<i32>;
+    var solid: bool;
+    loop {
+      cell = voxel >> vec3(u32(level));
+      // level sizes round down, so the last cells of odd-sized levels have no texel
+      // and are looked at more closely just like solid ones
+      solid = any(cell >= chunk_res >> vec3(u32(level)))
+        || textureLoad(occupancy, cell + vec3(0, 0, level_offset(chunk_res.x, level)), 0).r != 0u;
+      if !solid || level == LEAF_LEVEL {
+        break;
+      }
+      level -= 1;
     }
 
-    if (tzmin > tmin) {
-        tmin = tzmin;
+    let size = 1 << u32(level);
+    let lo = cell * size;
+    if solid {
+      let hit = march_voxels(ray, lo, min(lo + vec3(size), chunk_res), t, face, 0u);
+      if hit.id != 0u {
+        return hit;
+      }
     }
 
-    if (tzmax < tmax) {
-        tmax = tzmax;
+    // leave the cell through whichever face the ray reaches first
+    let t_max = (vec3<f32>(lo + max(ray.step, vec3(0)) * size) - ray.origin) * ray.inv_dir;
+    t = min(t_max.x, min(t_max.y, t_max.z));
+    voxel = clamp(vec3<i32>(floor(ray.origin + ray.dir * t)), lo, lo + vec3(size - 1));
+    if t_max.x <= t_max.y && t_max.x <= t_max.z {
+      voxel.x = select(lo.x - 1, lo.x + size, ray.step.x > 0);
+      face = vec3(-f32(ray.step.x), 0.0, 0.0);
+    } else if t_max.y <= t_max.z {
+      voxel.y = select(lo.y - 1, lo.y + size, ray.step.y > 0);
+      face = vec3(0.0, -f32(ray.step.y), 0.0);
+    } else {
+      voxel.z = select(lo.z - 1, lo.z + size, ray.step.z > 0);
+      face = vec3(0.0, 0.0, -f32(ray.step.z));
     }
-
-    var intersectionPoint: vec3<f32> = rayOrigin + rayDirection * tmax;
-    return intersectionPoint;
+    // the cell above was solid or the ray wouldn't be down here, no need to look
+    // at it again until the ray leaves it
+    if level < top && any(voxel >> vec3(u32(level + 1)) != lo >> vec3(u32(level + 1))) {
+      level += 1;
+    }
+  }
+  return Hit(0u, t, face);
 }
 
+/// Marches a ray starting inside the chunk, in voxel units, with whichever traversal
+/// the settings ask for. Inside a see-through block the occupancy levels say nothing
+/// about where it ends, so that's always marched voxel by voxel.
+fn march(ray: Ray, chunk_res: vec3<i32>, normal: vec3<f32>, medium: u32) -> Hit {
+  if uniforms.skip_empty_space != 0u && medium == 0u {
+    return march_mips(ray, chunk_res, normal);
+  }
+  return march_voxels(ray, vec3(0), chunk_res, 0.0, normal, medium);
+}
 
+/// Marches the chunk from `start`, a point on or inside the -1..1 cube, along `dir`.
+/// The hit distance is in the units of that cube as well, `normal` is the face of the
+/// cube `start` is on and `medium` the block it's inside of, 0 for air.
+fn RayCast(start: vec3<f32>, dir: vec3<f32>, normal: vec3<f32>, medium: u32) -> Hit {
+  let chunk_res = vec3<i32>(textureDimensions(voxel_data));
+  let ray = new_ray((start + 1.0) * vec3<f32>(chunk_res) / 2.0, dir);
 
+  var hit = march(ray, chunk_res, normal, medium);
+  hit.t *= 2.0 / f32(chunk_res.x);
+  return hit;
+}
 
+/// The block at `voxel`, counted from this chunk's first one, which may be in one of
+/// the chunks sharing a face with it. Anything past an edge or a corner is air.
+fn world_voxel(voxel: vec3<i32>, chunk_res: vec3<i32>) -> u32 {
+  let offset = select(vec3(0), vec3(-1), voxel < vec3(0))
+    + select(vec3(0), vec3(1), voxel >= chunk_res);
+  let local = voxel - offset * chunk_res;
+  if all(offset == vec3(0)) {
+    return textureLoad(voxel_data, local, 0).r;
+  }
+  if abs(offset.x) + abs(offset.y) + abs(offset.z) > 1 {
+    return 0u;
+  }
+  if offset.y < 0 {
+    return textureLoad(neighbour_down, local, 0).r;
+  } else if offset.y > 0 {
+    return textureLoad(neighbour_up, local, 0).r;
+  } else if offset.x < 0 {
+    return textureLoad(neighbour_west, local, 0).r;
+  } else if offset.x > 0 {
+    return textureLoad(neighbour_east, local, 0).r;
+  } else if offset.z < 0 {
+    return textureLoad(neighbour_north, local, 0).r;
+  }
+  return textureLoad(neighbour_south, local, 0).r;
+}
 
+/// How far shadow rays carry on into the chunks around, in voxels.
+const NEIGHBOUR_REACH: f32 = 64.0;
+
+/// `transmittance` past the edge of the chunk, from `t` to `end` along `ray` in voxel
+/// units, stepping voxel by voxel through the chunks around with `world_voxel`.
+/// `medium` is the block the ray leaves the chunk in.
+fn neighbour_transmittance(ray: Ray, chunk_res: vec3<i32>, t: f32, end: f32, medium: u32) -> vec3<f32> {
+  var voxel = vec3<i32>(floor(ray.origin + ray.dir * t));
+  var t_max = (vec3<f32>(voxel + max(ray.step, vec3(0))) - ray.origin) * ray.inv_dir;
+  let t_delta = abs(ray.inv_dir);
+  var t_enter = t;
+  var inside = medium;
+  var transmit = vec3(1.0);
+
+  for (var i = 0; i < MAX_STEPS && t_enter < end; i++) {
+    let id = world_voxel(voxel, chunk_res);
+    if id != inside {
+      if id != 0u {
+        let material = palette[id];
+        if material.transparency <= 0.0 {
+          return vec3(0.0);
+        }
+        transmit *= material.transparency;
+        if material.translucent == 0u {
+          transmit *= material.color.rgb;
+        }
+      }
+      inside = id;
+    }
 
+    let t_exit = min(min(t_max.x, min(t_max.y, t_max.z)), end);
+    if palette[inside].translucent != 0u {
+      transmit *= pow(palette[inside].color.rgb, vec3((t_exit - t_enter) / TINT_DEPTH));
+    }
+    t_enter = t_exit;
+    if t_max.x <= t_max.y && t_max.x <= t_max.z {
+      voxel.x += ray.step.x;
+      t_max.x += t_delta.x;
+    } else if t_max.y <= t_max.z {
+      voxel.y += ray.step.y;
+      t_max.y += t_delta.y;
+    } else {
+      voxel.z += ray.step.z;
+      t_max.z += t_delta.z;
+    }
+  }
+  return transmit;
+}
 
-fn RayCast(campos: vec3<f32>, dir: vec3<f32>) -> vec4<f32> {
-  let chunk_res = vec3(101.0);
-  let origin = campos * chunk_res / vec3(2.0);
+/// How much of the light coming from `distance` away along `dir` reaches `pos`:
+/// nothing behind an opaque block, tinted by every see-through one on the way the
+/// same as a view ray. Past the edge of the chunk the ray carries on through the
+/// ones around for up to `NEIGHBOUR_REACH` voxels. `pos` is a point on a face with
+/// `normal` in the -1..1 cube, `dir` and `distance` are in the same space.
+fn transmittance(pos: vec3<f32>, normal: vec3<f32>, dir: vec3<f32>, distance: f32) -> vec3<f32> {
+  let chunk_res = vec3<i32>(textureDimensions(voxel_data));
+  let scale = f32(chunk_res.x) / 2.0;
+  // start just off the face, or the ray would hit the voxel it leaves from
+  var ray = new_ray((pos + 1.0) * scale + normal * 1e-3, dir);
+  // `ray` moves along as the chunk is marched, the neighbours are marched from the face
+  let from_face = ray;
+  let reach = distance * scale;
+  // faces on the chunk's edge start outside it, move up to where the ray enters
+  let span = intersect_box(ray, vec3(0.0), vec3<f32>(chunk_res));
+  if span.x > span.y {
+    // the face may be under water in the chunk next door
+    var medium = world_voxel(vec3<i32>(floor(ray.origin)), chunk_res);
+    if palette[medium].transparency <= 0.0 {
+      medium = 0u;
+    }
+    return neighbour_transmittance(ray, chunk_res, 0.0, min(reach, NEIGHBOUR_REACH), medium);
+  }
+  let end = min(reach, span.y) - span.x;
+  if end < 0.0 {
+    return vec3(1.0);
+  }
+  let start = ray.origin + ray.dir * span.x;
 
-  let RayStepX = sqrt(1.0 + pow(dir.y / dir.x, 2.0) + pow(dir.z / dir.x, 2.0));
-  let RayStepY = sqrt(1.0 + pow(dir.x / dir.y, 2.0) + pow(dir.z / dir.y, 2.0));
-  let RayStepZ = sqrt(1.0 + pow(dir.x / dir.z, 2.0) + pow(dir.y / dir.z, 2.0));
+  var transmit = vec3(1.0);
+  // the face may be under water
+  var medium = textureLoad(voxel_data, clamp(vec3<i32>(floor(start)), vec3(0), chunk_res - 1), 0).r;
+  if palette[medium].transparency <= 0.0 {
+    medium = 0u;
+  }
+  var t = 0.0;
+  var face = vec3(0.0);
+  for (var layer = 0; layer < MAX_LAYERS; layer++) {
+    ray = new_ray(start + ray.dir * t, ray.dir);
+    ray.max_t = end - t;
+    let hit = march(ray, chunk_res, face, medium);
+    if palette[medium].translucent != 0u {
+      transmit *= pow(palette[medium].color.rgb, vec3(min(hit.t, ray.max_t) / TINT_DEPTH));
+    }
+    // id 0 is either the way out of a see-through block or out of reach
+    if hit.id == 0u && (medium == 0u || hit.t >= ray.max_t - 1e-3) {
+      break;
+    }
+    medium = hit.id;
+    face = hit.normal;
+    // just past the face, or the next march would start in the voxel the ray left
+    t += hit.t + 1e-4;
+    if t >= end {
+      break;
+    }
+    if hit.id == 0u {
+      continue;
+    }
 
-  var StepVectorX : f32;
-  var StepVectorY : f32;
-  var StepVectorZ : f32;
+    let material = palette[hit.id];
+    if material.transparency <= 0.0 {
+      return vec3(0.0);
+    }
+    transmit *= material.transparency;
+    if material.translucent == 0u {
+      transmit *= material.color.rgb;
+    }
+  }
+  if reach <= span.y {
+    return transmit;
+  }
+  // just past the side the ray leaves through, in the chunk next to it
+  let exit = span.y + 1e-4;
+  return transmit
+    * neighbour_transmittance(from_face, chunk_res, exit, min(reach, exit + NEIGHBOUR_REACH), medium);
+}
 
-  var RayLenghX : f32;
-  var RayLenghY : f32;
-  var RayLenghZ : f32;
+/// Whether `voxel` is an opaque block, it may be in one of the chunks around, see
+/// `world_voxel`.
+fn is_solid(voxel: vec3<i32>, chunk_res: vec3<i32>) -> bool {
+  let id = world_voxel(voxel, chunk_res);
+  return id != 0u && palette[id].transparency <= 0.0;
+}
 
-  var MapCheckX = floor(origin.x);
-  var MapCheckY = floor(origin.y);
-  var MapCheckZ = floor(origin.z);
+/// Minecraft style occlusion of the face at `pos` with `normal`, both in voxel units.
+/// Each corner of the face gets darker with every voxel next to it in the layer in
+/// front, and the corners are blended bilinearly across the face.
+fn vertex_ao(pos: vec3<f32>, normal: vec3<f32>, chunk_res: vec3<i32>) -> f32 {
+  // the two axes along the face
+  let u = abs(normal).yzx;
+  let v = abs(normal).zxy;
+  // the air voxel in front of the face
+  let front = vec3<i32>(floor(pos + normal * 0.5));
+  let ui = vec3<i32>(u);
+  let vi = vec3<i32>(v);
+
+  var corners: array<f32, 4>;
+  for (var i = 0; i < 4; i++) {
+    let su = select(-1, 1, (i & 1) != 0);
+    let sv = select(-1, 1, (i & 2) != 0);
+    let side_u = is_solid(front + ui * su, chunk_res);
+    let side_v = is_solid(front + vi * sv, chunk_res);
+    let corner = is_solid(front + ui * su + vi * sv, chunk_res);
+    // with both sides solid the corner is hidden whatever is in it
+    if side_u && side_v {
+      corners[i] = 0.0;
+    } else {
+      corners[i] = 3.0 - f32(side_u) - f32(side_v) - f32(corner);
+    }
+  }
 
+  let f = vec2(dot(fract(pos), u), dot(fract(pos), v));
+  let ao = mix(mix(corners[0], corners[1], f.x), mix(corners[2], corners[3], f.x), f.y);
+  return (ao + 1.0) / 4.0;
+}
 
-  if dir.x < 0.0 {
-    RayLenghX = (origin.x - MapCheckX) * RayStepX;
-    StepVectorX = -1.0;
-  } else {
-    RayLenghX = ((MapCheckX + 1.0) - origin.x) * RayStepX;
-    StepVectorX = 1.0;
+/// Rays cast by `ray_ao` and how far they go in voxels.
+const AO_RAYS: i32 = 8;
+const AO_DISTANCE: f32 = 4.0;
+
+/// The first block `ray` runs into before `max_t`, 0 if none. Steps voxel by voxel
+/// with `world_voxel`, so the ray may go on into the chunks around.
+fn first_block(ray: Ray, chunk_res: vec3<i32>) -> u32 {
+  var voxel = vec3<i32>(floor(ray.origin));
+  var t_max = (vec3<f32>(voxel + max(ray.step, vec3(0))) - ray.origin) * ray.inv_dir;
+  let t_delta = abs(ray.inv_dir);
+  var t_enter = 0.0;
+
+  while t_enter <= ray.max_t {
+    let id = world_voxel(voxel, chunk_res);
+    if id != 0u {
+      return id;
+    }
+    if t_max.x <= t_max.y && t_max.x <= t_max.z {
+      voxel.x += ray.step.x;
+      t_enter = t_max.x;
+      t_max.x += t_delta.x;
+    } else if t_max.y <= t_max.z {
+      voxel.y += ray.step.y;
+      t_enter = t_max.y;
+      t_max.y += t_delta.y;
+    } else {
+      voxel.z += ray.step.z;
+      t_enter = t_max.z;
+      t_max.z += t_delta.z;
+    }
   }
+  return 0u;
+}
 
-  if dir.y < 0.0 {
-    RayLenghY = (origin.y - MapCheckY) * RayStepY;
-    StepVectorY = -1.0;
-  } else {
-    RayLenghY = ((MapCheckY + 1.0) - origin.y) * RayStepY;
-    StepVectorY = 1.0;
+/// Fraction of `AO_RAYS` short rays from the face at `pos` with `normal` that get
+/// away without hitting anything opaque, both in voxel units. The rays fan out around the
+/// normal the same way on every face so the result doesn't flicker.
+fn ray_ao(pos: vec3<f32>, normal: vec3<f32>, chunk_res: vec3<i32>) -> f32 {
+  let origin = pos + normal * 1e-3;
+  let u = abs(normal).yzx;
+  let v = abs(normal).zxy;
+
+  var open = 0;
+  for (var i = 0; i < AO_RAYS; i++) {
+    let angle = f32(i) * 6.2831853 / f32(AO_RAYS);
+    // alternate between steep and shallow rays
+    let spread = select(0.5, 1.5, (i & 1) != 0);
+    var ray = new_ray(origin, normalize(normal + (u * cos(angle) + v * sin(angle)) * spread));
+    ray.max_t = AO_DISTANCE;
+    // see-through blocks let the ambient light by, whatever is behind them
+    let id = first_block(ray, chunk_res);
+    if id == 0u || palette[id].transparency > 0.0 {
+      open += 1;
+    }
   }
+  return f32(open) / f32(AO_RAYS);
+}
 
-  if dir.z < 0.0 {
-    RayLenghZ = (origin.z - MapCheckZ) * RayStepZ;
-    StepVectorZ = -1.0;
-  } else {
-    RayLenghZ = ((MapCheckZ + 1.0) - origin.z) * RayStepZ;
-    StepVectorZ = 1.0;
+/// How much ambient light reaches the face at `pos`, a point in the -1..1 cube.
+fn ambient_occlusion(pos: vec3<f32>, normal: vec3<f32>) -> f32 {
+  // with the camera inside a voxel there's no face to look around
+  if all(normal == vec3(0.0)) {
+    return 1.0;
   }
+  let chunk_res = vec3<i32>(textureDimensions(voxel_data));
+  let voxel_pos = (pos + 1.0) * vec3<f32>(chunk_res) / 2.0;
+  switch lighting.ambient_occlusion {
+    case 1u: {
+      return vertex_ao(voxel_pos, normal, chunk_res);
+    }
+    case 2u: {
+      return ray_ao(voxel_pos, normal, chunk_res);
+    }
+    default: {
+      return 1.0;
+    }
+  }
+}
 
-  let max_dis = 1000.0;
-  var current_dis = 0.0;
-
-  while current_dis < max_dis {
-
-    let val = textureLoad(voxel_data, vec3i(i32(MapCheckX), i32(MapCheckY), i32(MapCheckZ)), 0);
+/// Sun light bounced towards the face at `pos`, a point in the -1..1 cube, blended
+/// from the probes in front of it. Probes stuck in solid blocks have no coverage and
+/// drop out of the blend.
+fn bounce_light(pos: vec3<f32>, normal: vec3<f32>) -> vec3<f32> {
+  let chunk_res = vec3<f32>(textureDimensions(voxel_data));
+  // half a voxel out, so the blend leans towards the air side of the face
+  let uv = (pos + 1.0) / 2.0 + normal * 0.5 / chunk_res;
+  let probe = textureSampleLevel(probes, probe_sampler, uv, 0.0);
+  return probe.rgb / max(probe.a, 1e-3);
+}
 
-    if any(val.r != 0u) {
-      return vec4(MapCheckX / chunk_res.x, MapCheckY / chunk_res.y, MapCheckZ / chunk_res.z, 1.0);
+/// Light from every point light that reaches the face at `pos` with `normal`, both
+/// in the -1..1 cube of a chunk placed at `model_position` turned by `model_rotation`.
+fn point_light(pos: vec3<f32>, normal: vec3<f32>, model_position: vec3<f32>, model_rotation: mat3x3<f32>) -> vec3<f32> {
+  var total = vec3(0.0);
+  for (var i = 0u; i < point_lights.count; i++) {
+    let light = point_lights.lights[i];
+    let to_light = (light.position - model_position) * model_rotation - pos;
+    let distance = length(to_light);
+    if distance >= light.radius {
+      continue;
     }
+    let dir = to_light / distance;
+    let facing = dot(normal, dir);
+    if facing <= 0.0 {
+      continue;
+    }
+    var reach = vec3(1.0);
+    if light.shadows != 0u {
+      reach = transmittance(pos, normal, dir, distance);
+    }
+    let falloff = 1.0 - distance / light.radius;
+    total += light.color * reach * facing * falloff * falloff;
+  }
+  return total;
+}
 
+/// How bright a light level looks, each level down is a fifth darker like Minecraft's.
+fn level_brightness(level: u32) -> f32 {
+  return select(pow(0.8, f32(15u - level)), 0.0, level == 0u);
+}
 
-    let min_ray_lengh = min(RayLenghX, min(RayLenghY, RayLenghZ));
-
-    if min_ray_lengh == RayLenghX {
-      MapCheckX += StepVectorX;
-      current_dis = RayLenghX;
-      RayLenghX += RayStepX;
+/// The voxel in front of the face at `pos` with `normal`, a point in the -1..1 cube.
+fn front_voxel(pos: vec3<f32>, normal: vec3<f32>) -> vec3<i32> {
+  let chunk_res = vec3<f32>(textureDimensions(voxel_data));
+  return vec3<i32>(floor((pos + 1.0) * chunk_res / 2.0 + normal * 0.5));
+}
 
-    } else if min_ray_lengh == RayLenghY {
-      MapCheckY += StepVectorY;
-      current_dis = RayLenghY;
-      RayLenghY += RayStepY;
+/// Sky and block light brightness of the air in front of the face at `pos`, a point
+/// in the -1..1 cube. Faces on the chunk's edge look into the neighbour's voxels
+/// kept in the border around the levels.
+fn light_level(pos: vec3<f32>, normal: vec3<f32>) -> vec2<f32> {
+  let front = front_voxel(pos, normal);
+  if lighting.light_levels == 0u || all(normal == vec3(0.0)) {
+    return vec2(1.0, 0.0);
+  }
+  // the border starts the texture, so the chunk's voxels are one in
+  let level = textureLoad(light_levels, front + vec3(1), 0).r;
+  return vec2(level_brightness(level >> 4u), level_brightness(level & 15u));
+}
 
-    } else {
-      MapCheckZ += StepVectorZ;
-      current_dis = RayLenghZ;
-      RayLenghZ += RayStepZ;
-    }
+struct FragmentOutput {
+  @location(0) color: vec4<f32>,
+  @builtin(frag_depth) depth: f32,
+}
 
-    if MapCheckX > chunk_res.x || MapCheckY > chunk_res.y || MapCheckZ > chunk_res.y || MapCheckX < 0.0 || MapCheckY < 0.0 || MapCheckZ < 0.0 {
-      break;
+/// Lights the face with `normal` of block `id` at `pos`, in the -1..1 cube.
+fn shade(id: u32, pos: vec3<f32>, normal: vec3<f32>, model_position: vec3<f32>, model_rotation: mat3x3<f32>) -> vec3<f32> {
+  let material = palette[id];
+  // chunks are only ever moved and rotated, so the normal needs no inverse transpose
+  // and the sun goes into chunk space the same way as the camera
+  let sun = lighting.sun_direction * model_rotation;
+  var sun_light = vec3(max(dot(normal, sun), 0.0));
+  let levels = light_level(pos, normal);
+  if lighting.shadows != 0u && sun_light.x > 0.0 {
+    sun_light *= transmittance(pos, normal, sun, lighting.shadow_distance);
+    // shadow rays only go so far, the sky light level says whether anything
+    // further away is overhead. The border around the levels stays dark until
+    // the neighbour there is lit, so faces on the chunk's edge leave it to the ray
+    let front = front_voxel(pos, normal);
+    let chunk_res = vec3<i32>(textureDimensions(voxel_data));
+    if all(front >= vec3(0)) && all(front < chunk_res) {
+      sun_light *= levels.x;
     }
   }
-
-  return vec4(0.1);
+  let indirect = lighting.ambient_color * levels.x + bounce_light(pos, normal)
+    + lighting.block_light_color * levels.y;
+  let ambient = indirect * ambient_occlusion(pos, normal);
+  let direct = lighting.sun_color * sun_light
+    + point_light(pos, normal, model_position, model_rotation);
+  let light = ambient + direct;
+  return material.color.rgb * light + material.emissive.rgb;
 }
 
+/// Optical depth straight up through the atmosphere, Rayleigh scattering is what
+/// makes the sky blue and Mie the haze around the sun.
+const RAYLEIGH: vec3<f32> = vec3(0.046, 0.108, 0.265);
+const MIE: f32 = 0.025;
+/// How much more the haze scatters forwards than back, Henyey-Greenstein `g`.
+const MIE_G: f32 = 0.76;
+/// Brightness of scattered sun light relative to `lighting.sun_color`.
+const SKY_INTENSITY: f32 = 12.0;
+/// Cosine of the sun's angular radius, a bit larger than the real one.
+const SUN_DISC_COS: f32 = 0.9995;
+const SUN_DISC_INTENSITY: f32 = 20.0;
+const PI: f32 = 3.14159265;
+
+/// How many times more air there is towards `y`, the up component of a unit
+/// direction, than straight up. Kasten and Young's fit, so the horizon stays finite.
+fn air_mass(y: f32) -> f32 {
+  let zenith = degrees(acos(clamp(y, 0.0, 1.0)));
+  return 1.0 / (max(y, 0.0) + 0.50572 * pow(96.07995 - zenith, -1.6364));
+}
 
+/// Colour of the sky looking along the world space `dir`, single scattering of the
+/// sun light with a disc where the sun is. Below the horizon it fades to dark ground.
+fn sky(dir: vec3<f32>) -> vec3<f32> {
+  let view = normalize(dir);
+  let sun = normalize(lighting.sun_direction);
+  let mu = dot(view, sun);
+
+  // sun light left after passing through the air towards the point
+  let sun_extinction = exp(-(RAYLEIGH + MIE) * air_mass(sun.y));
+  let rayleigh_phase = 3.0 / (16.0 * PI) * (1.0 + mu * mu);
+  let g2 = MIE_G * MIE_G;
+  let mie_phase = (1.0 - g2) / (4.0 * PI * pow(1.0 + g2 - 2.0 * MIE_G * mu, 1.5));
+  let depth = (RAYLEIGH + MIE) * air_mass(view.y);
+  let scattered = (RAYLEIGH * rayleigh_phase + MIE * mie_phase) / (RAYLEIGH + MIE)
+    * (1.0 - exp(-depth));
+  var color = lighting.sun_color * SKY_INTENSITY * sun_extinction * scattered;
+
+  let disc = smoothstep(SUN_DISC_COS - 0.0002, SUN_DISC_COS, mu);
+  color += lighting.sun_color * SUN_DISC_INTENSITY * disc * exp(-depth);
+
+  let ground = color * 0.3;
+  return mix(color, ground, smoothstep(0.0, -0.05, view.y));
+}
 
+/// See-through surfaces a view ray goes through before giving up on what's behind.
+const MAX_LAYERS: i32 = 8;
+/// Voxels of a translucent block it takes to tint light by the block's colour once.
+const TINT_DEPTH: f32 = 10.0;
 
 @fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+fn fs_main(in: VertexOutput) -> FragmentOutput {
+  let model_matrix = mat4x4<f32>(
+    in.model_matrix_0,
+    in.model_matrix_1,
+    in.model_matrix_2,
+    in.model_matrix_3,
+  );
   let model_position = in.model_matrix_3.xyz;
   let model_rotation = mat3x3(
     in.model_matrix_0.xyz,
@@ -234,15 +769,112 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
 
 
   let cam_pos = (vec3(uniforms.cam_x, uniforms.cam_y, uniforms.cam_z) - model_position) * model_rotation;
-  let dir = normalize(cam_pos - in.uv_cords);
-  let val = RayCast(cam_pos, dir);
+  // only the back faces are drawn so there's a fragment even with the camera inside
+  // the chunk, the march starts where the view ray enters it
+  let ray = new_ray(cam_pos, normalize(in.uv_cords - cam_pos));
+  let span = intersect_box(ray, vec3(-1.0), vec3(1.0));
+  if span.x > span.y {
+    discard;
+  }
 
-  let min = vec3(-1.0);
-  let max = vec3(1.0);
+  let chunk_res = vec3<f32>(textureDimensions(voxel_data));
+  // a camera under water starts out inside it
+  var medium = 0u;
+  if span.x == 0.0 {
+    let voxel = clamp(vec3<i32>(floor((cam_pos + 1.0) * chunk_res / 2.0)), vec3(0), vec3<i32>(chunk_res) - 1);
+    let id = textureLoad(voxel_data, voxel, 0).r;
+    if palette[id].transparency > 0.0 {
+      medium = id;
+    }
+  }
 
-  let start_pos = rayCubeIntersection(cam_pos, dir, min, max) + vec3(1.0);
+  // see-through blocks don't stop the ray, each surface it goes through adds its
+  // own colour and lets less of whatever is behind it through
+  var color = vec3(0.0);
+  var transmit = vec3(1.0);
+  var opaque = false;
+  // where the ray first hit anything, -1 for nowhere
+  var first_t = -1.0;
+  var t = span.x;
+  var normal = entry_normal(ray, vec3(-1.0), vec3(1.0));
+  for (var layer = 0; layer < MAX_LAYERS; layer++) {
+    let hit = RayCast(ray.origin + ray.dir * t, ray.dir, normal, medium);
+    if palette[medium].translucent != 0u {
+      transmit *= pow(palette[medium].color.rgb, vec3(hit.t * chunk_res.x / 2.0 / TINT_DEPTH));
+    }
+    let hit_t = t + hit.t;
+    // id 0 is either the way out of a see-through block or out of the chunk
+    if hit.id == 0u && (medium == 0u || hit_t >= span.y - 1e-5) {
+      break;
+    }
+    if first_t < 0.0 {
+      first_t = hit_t;
+    }
+    medium = hit.id;
+    normal = hit.normal;
+    // just past the face, or the next march would start in the voxel the ray left
+    t = hit_t + 1e-4;
+    if hit.id == 0u {
+      continue;
+    }
 
-  let ray_res = RayCast(start_pos, dir * vec3(-1.0));
+    let material = palette[hit.id];
+    let surface = shade(hit.id, ray.origin + ray.dir * hit_t, hit.normal, model_position, model_rotation);
+    color += transmit * surface * (1.0 - material.transparency);
+    if material.transparency <= 0.0 {
+      opaque = true;
+      break;
+    }
+    transmit *= material.transparency;
+    if material.translucent == 0u {
+      transmit *= material.color.rgb;
+    }
+  }
+
+  var out: FragmentOutput;
+  if first_t < 0.0 && all(transmit == vec3(1.0)) {
+    // on the far plane, so it only shows where nothing was drawn behind the chunk
+    out.color = vec4(sky(model_rotation * ray.dir), 1.0);
+    out.depth = 1.0;
+    return out;
+  }
 
-  return ray_res;
+  // whatever the chunk lets through is left to the blend with what's behind it
+  var alpha = 1.0;
+  if !opaque {
+    alpha = 1.0 - (transmit.r + transmit.g + transmit.b) / 3.0;
+  }
+  out.color = vec4(color / max(alpha, 1e-3), alpha);
+  if first_t < 0.0 {
+    out.depth = in.clip_position.z;
+  } else {
+    // depth of the first voxel rather than the cube, so meshes and other chunks
+    // get depth tested against what's actually drawn
+    let clip = camera.view_proj * model_matrix * vec4(ray.origin + ray.dir * first_t, 1.0);
+    out.depth = clip.z / clip.w;
+  }
+  return out;
+}
+
+struct SkyOutput {
+  @builtin(position) clip_position: vec4<f32>,
+  @location(0) ndc: vec2<f32>,
+}
+
+/// A triangle covering the whole screen, drawn behind everything else.
+@vertex
+fn vs_sky(@builtin(vertex_index) index: u32) -> SkyOutput {
+  let uv = vec2(f32((index << 1u) & 2u), f32(index & 2u));
+  var out: SkyOutput;
+  out.ndc = uv * 2.0 - 1.0;
+  out.clip_position = vec4(out.ndc, 0.0, 1.0);
+  return out;
+}
+
+@fragment
+fn fs_sky(in: SkyOutput) -> @location(0) vec4<f32> {
+  // from the eye through the pixel's point on the near plane
+  let near = camera.inv_view_proj * vec4(in.ndc, 0.0, 1.0);
+  let eye = vec3(uniforms.cam_x, uniforms.cam_y, uniforms.cam_z);
+  return vec4(sky(near.xyz / near.w - eye), 1.0);
 }
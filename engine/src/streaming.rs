@@ -0,0 +1,189 @@
+use crate::{
+    chunk_gen::TerrainGenerator,
+    world::{ChunkCoord, NewChunk, Relit, World, CHUNK_WORLD_SIZE},
+};
+use cgmath::{EuclideanSpace, InnerSpace, Point3};
+use std::{
+    collections::HashSet,
+    sync::{
+        mpsc::{channel, Receiver, Sender},
+        Arc,
+    },
+};
+
+/// Keeps the chunks within `radius` of the camera loaded, generating and lighting
+/// them on the tokio blocking pool and uploading a few finished ones per frame.
+/// Chunks whose lighting goes stale are relit on the pool as well.
+pub struct ChunkStreamer {
+    /// Load radius in chunks, measured from the chunk the camera is in.
+    pub radius: i32,
+    /// Upper bound on texture uploads per `update`, keeps frame times flat.
+    pub uploads_per_frame: usize,
+    /// Upper bound on `LightJob`s out at once, so a new sun is spread over a few
+    /// frames rather than taking every thread.
+    pub relights_in_flight: usize,
+    /// Upper bound on chunks whose probes are baked per `update` where the device
+    /// does it in a compute pass, see `World::bake_probes`.
+    pub probe_bakes_per_frame: usize,
+    runtime: tokio::runtime::Handle,
+    pending: HashSet<ChunkCoord>,
+    relights: usize,
+    sender: Sender<Finished>,
+    receiver: Receiver<Finished>,
+}
+
+/// Work coming back from the blocking pool.
+enum Finished {
+    /// A new chunk, lit right after it was generated or copied from the stored
+    /// edits. The generator is along so results from before a `World::load`
+    /// swapped generators can be thrown away.
+    Chunk {
+        coord: ChunkCoord,
+        chunk: NewChunk,
+        generator: Arc<dyn TerrainGenerator>,
+        restored: bool,
+    },
+    Relit(Relit),
+}
+
+/// The chunk whose bounding cube contains `pos`.
+pub fn chunk_at(pos: Point3<f32>) -> ChunkCoord {
+    let half = CHUNK_WORLD_SIZE / 2.0;
+    pos.to_vec()
+        .map(|v| ((v + half) / CHUNK_WORLD_SIZE).floor() as i32)
+}
+
+fn in_range(center: ChunkCoord, coord: ChunkCoord, radius: i32) -> bool {
+    (coord - center).magnitude2() <= radius * radius
+}
+
+impl ChunkStreamer {
+    /// Must be called from inside a tokio runtime. Chunks come from the world's
+    /// generator, or from its stored edits if it has any for them.
+    pub fn new(radius: i32) -> Self {
+        let (sender, receiver) = channel();
+
+        Self {
+            radius,
+            uploads_per_frame: 2,
+            relights_in_flight: 4,
+            probe_bakes_per_frame: 4,
+            runtime: tokio::runtime::Handle::current(),
+            pending: HashSet::new(),
+            relights: 0,
+            sender,
+            receiver,
+        }
+    }
+
+    /// Nothing is loaded before the world knows what to light chunks with, from its
+    /// first `World::upload_dirty`.
+    pub fn update(
+        &mut self,
+        world: &mut World,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        eye: Point3<f32>,
+    ) {
+        let center = chunk_at(eye);
+
+        // one chunk of slack so standing on a border doesn't load and evict every frame
+        let evict = world
+            .chunk_coords()
+            .filter(|c| !in_range(center, **c, self.radius + 1))
+            .copied()
+            .collect::<Vec<_>>();
+        for coord in evict {
+            world.remove_chunk(device, coord);
+        }
+        let Some(light) = world.bounce_light().cloned() else {
+            return;
+        };
+
+        let r = self.radius;
+        for x in -r..=r {
+            for y in -r..=r {
+                for z in -r..=r {
+                    let coord = center + ChunkCoord::new(x, y, z);
+                    if !in_range(center, coord, r)
+                        || world.contains_chunk(coord)
+                        || self.pending.contains(&coord)
+                    {
+                        continue;
+                    }
+
+                    // stored edits stay in the world until the lit copy is back
+                    self.pending.insert(coord);
+                    let stored = world.stored_chunk(coord).cloned();
+                    let borders = world.borders(coord);
+                    // the probes see into the neighbours, unless they're baked on the GPU
+                    let resolution = (!world.probes_on_gpu()).then(|| world.probe_resolution());
+                    let neighbours = match resolution {
+                        Some(_) => world.neighbour_voxels(coord),
+                        None => Default::default(),
+                    };
+                    let sender = self.sender.clone();
+                    let generator = world.generator().clone();
+                    let light = light.clone();
+                    self.runtime.spawn_blocking(move || {
+                        let restored = stored.is_some();
+                        let voxels = stored.unwrap_or_else(|| generator.generate(coord));
+                        let chunk =
+                            NewChunk::lit(voxels, &borders, &neighbours, &light, resolution);
+                        // the receiver only goes away with the streamer itself
+                        let _ = sender.send(Finished::Chunk {
+                            coord,
+                            chunk,
+                            generator,
+                            restored,
+                        });
+                    });
+                }
+            }
+        }
+
+        for finished in self.receiver.try_iter().take(self.uploads_per_frame) {
+            match finished {
+                Finished::Chunk {
+                    coord,
+                    chunk,
+                    generator,
+                    restored,
+                } => {
+                    self.pending.remove(&coord);
+                    // a load since may have stored other edits for the chunk, or dropped them
+                    let current = match world.stored_chunk(coord) {
+                        Some(stored) => restored && stored.packed() == chunk.voxels().packed(),
+                        None => !restored,
+                    };
+                    if in_range(center, coord, self.radius + 1)
+                        && Arc::ptr_eq(&generator, world.generator())
+                        && current
+                    {
+                        world.insert_chunk(device, queue, coord, chunk);
+                    }
+                }
+                Finished::Relit(relit) => {
+                    self.relights -= 1;
+                    world.finish_light_job(queue, relit);
+                }
+            }
+        }
+
+        // stale lighting, from a new sun or a chunk baked with an old one, is redone
+        // nearest first
+        while self.relights < self.relights_in_flight {
+            let Some(job) = world.next_light_job(eye) else {
+                break;
+            };
+            self.relights += 1;
+            let sender = self.sender.clone();
+            self.runtime.spawn_blocking(move || {
+                let _ = sender.send(Finished::Relit(job.run()));
+            });
+        }
+
+        world.bake_probes(device, queue, eye, self.probe_bakes_per_frame);
+        world.update_instance_buffer(device);
+    }
+}
@@ -1,3 +1,4 @@
+use crate::voxel::VoxelRegion;
 use image::GenericImageView;
 
 pub struct Texture {
@@ -94,6 +95,108 @@ impl Texture {
         }
     }
 
+    /// An empty `R8Uint` volume for the raymarcher to `textureLoad` from.
+    pub fn create_voxel_texture(device: &wgpu::Device, size: wgpu::Extent3d, label: &str) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::R8Uint,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// An empty `Rgba8Unorm` volume that is sampled with linear filtering, for values
+    /// that are meant to be blended between texels. It can be copied from as well,
+    /// for what a compute pass baked into it.
+    pub fn create_filtered_volume(
+        device: &wgpu::Device,
+        size: wgpu::Extent3d,
+        label: &str,
+    ) -> Self {
+        let texture = device.create_texture(&wgpu::TextureDescriptor {
+            label: Some(label),
+            size,
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D3,
+            format: wgpu::TextureFormat::Rgba8Unorm,
+            usage: wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_DST
+                | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+
+        let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+        let sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            address_mode_u: wgpu::AddressMode::ClampToEdge,
+            address_mode_v: wgpu::AddressMode::ClampToEdge,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+
+        Self {
+            texture,
+            view,
+            sampler,
+        }
+    }
+
+    /// Writes a tightly packed x-major block of voxels into `region` of a texture
+    /// made by `create_voxel_texture`.
+    pub fn write_voxels(&self, queue: &wgpu::Queue, region: VoxelRegion, data: &[u8]) {
+        self.write_texels(queue, region, data, 1);
+    }
+
+    /// Same as `write_voxels` for textures with `texel_size` bytes per texel.
+    pub fn write_texels(
+        &self,
+        queue: &wgpu::Queue,
+        region: VoxelRegion,
+        data: &[u8],
+        texel_size: u32,
+    ) {
+        let extent = region.extent();
+        queue.write_texture(
+            wgpu::ImageCopyTexture {
+                aspect: wgpu::TextureAspect::All,
+                texture: &self.texture,
+                mip_level: 0,
+                origin: region.origin(),
+            },
+            data,
+            wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(extent.width * texel_size),
+                rows_per_image: Some(extent.height),
+            },
+            extent,
+        );
+    }
+
     pub fn from_bytes(
         device: &wgpu::Device,
         queue: &wgpu::Queue,
@@ -0,0 +1,486 @@
+use crate::texture::Texture;
+
+/// Past this many separate edits per frame they are merged into one bounding box,
+/// a single larger upload beats lots of tiny ones.
+pub const MAX_DIRTY_REGIONS: usize = 32;
+
+/// Axis-aligned box of voxels, `min` inclusive and `max` exclusive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VoxelRegion {
+    pub min: [u32; 3],
+    pub max: [u32; 3],
+}
+
+impl VoxelRegion {
+    pub fn voxel(x: u32, y: u32, z: u32) -> Self {
+        Self {
+            min: [x, y, z],
+            max: [x + 1, y + 1, z + 1],
+        }
+    }
+
+    pub fn union(&self, other: &Self) -> Self {
+        Self {
+            min: [0, 1, 2].map(|i| self.min[i].min(other.min[i])),
+            max: [0, 1, 2].map(|i| self.max[i].max(other.max[i])),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        (0..3).any(|i| self.max[i] <= self.min[i])
+    }
+
+    /// True if the boxes overlap or share part of a face. Boxes that only meet at an
+    /// edge or a corner don't count, their union would be mostly voxels neither of
+    /// them covers. Even for touching boxes the union can take in a few extra voxels
+    /// unless the shared faces line up.
+    pub fn touches(&self, other: &Self) -> bool {
+        let meet = (0..3).all(|i| self.min[i] <= other.max[i] && other.min[i] <= self.max[i]);
+        let overlap = (0..3)
+            .filter(|&i| self.min[i] < other.max[i] && other.min[i] < self.max[i])
+            .count();
+        meet && overlap >= 2
+    }
+
+    pub fn origin(&self) -> wgpu::Origin3d {
+        wgpu::Origin3d {
+            x: self.min[0],
+            y: self.min[1],
+            z: self.min[2],
+        }
+    }
+
+    pub fn extent(&self) -> wgpu::Extent3d {
+        wgpu::Extent3d {
+            width: self.max[0] - self.min[0],
+            height: self.max[1] - self.min[1],
+            depth_or_array_layers: self.max[2] - self.min[2],
+        }
+    }
+}
+
+/// Block ids stored as indices into a small palette, bit-packed into `u64` words.
+/// Chunks are mostly a handful of block types, so a 100³ chunk of terrain takes
+/// 2 bits per voxel instead of 8, and a uniform one takes no words at all.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PackedVoxels {
+    len: usize,
+    palette: Vec<u8>,
+    /// Bits per index, always 0, 1, 2, 4 or 8 so no index straddles two words.
+    bits: u32,
+    words: Vec<u64>,
+}
+
+/// Smallest supported index width that can address `palette_len` entries.
+fn bits_for(palette_len: usize) -> u32 {
+    match palette_len {
+        0..=1 => 0,
+        2 => 1,
+        3..=4 => 2,
+        5..=16 => 4,
+        _ => 8,
+    }
+}
+
+impl PackedVoxels {
+    /// `len` voxels that are all `id`.
+    pub fn uniform(len: usize, id: u8) -> Self {
+        Self {
+            len,
+            palette: vec![id],
+            bits: 0,
+            words: vec![],
+        }
+    }
+
+    pub fn from_raw(data: &[u8]) -> Self {
+        let mut seen = [false; 256];
+        let mut palette = vec![];
+        for id in data {
+            if !seen[*id as usize] {
+                seen[*id as usize] = true;
+                palette.push(*id);
+            }
+        }
+        if palette.is_empty() {
+            palette.push(0);
+        }
+
+        let mut indices = [0u8; 256];
+        for (i, id) in palette.iter().enumerate() {
+            indices[*id as usize] = i as u8;
+        }
+
+        let mut packed = Self {
+            len: data.len(),
+            bits: bits_for(palette.len()),
+            palette,
+            words: vec![],
+        };
+        packed.pack(data.iter().map(|id| indices[*id as usize] as u64));
+        packed
+    }
+
+    /// Rebuilds packed data as written by `palette`, `bits` and `words`, checking
+    /// that the parts fit together.
+    pub fn from_parts(
+        len: usize,
+        palette: Vec<u8>,
+        bits: u32,
+        words: Vec<u64>,
+    ) -> Result<Self, String> {
+        if palette.is_empty() || palette.len() > 256 {
+            return Err(format!("palette has {} entries", palette.len()));
+        }
+        if ![0, 1, 2, 4, 8].contains(&bits) || palette.len() > 1 << bits {
+            return Err(format!(
+                "{bits} bits can't index {} palette entries",
+                palette.len()
+            ));
+        }
+        let packed = Self {
+            len,
+            palette,
+            bits,
+            words,
+        };
+        if packed.words.len() != packed.word_count() {
+            return Err(format!(
+                "expected {} words, got {}",
+                packed.word_count(),
+                packed.words.len()
+            ));
+        }
+        if packed.indices().any(|i| i as usize >= packed.palette.len()) {
+            return Err("index points past the end of the palette".to_string());
+        }
+        Ok(packed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn palette(&self) -> &[u8] {
+        &self.palette
+    }
+
+    pub fn bits(&self) -> u32 {
+        self.bits
+    }
+
+    pub fn words(&self) -> &[u64] {
+        &self.words
+    }
+
+    /// Bytes held on the heap, for comparing against the unpacked `len`.
+    pub fn memory_usage(&self) -> usize {
+        self.palette.capacity() + self.words.capacity() * std::mem::size_of::<u64>()
+    }
+
+    fn per_word(&self) -> usize {
+        64 / self.bits as usize
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    fn word_count(&self) -> usize {
+        match self.bits {
+            0 => 0,
+            _ => self.len.div_ceil(self.per_word()),
+        }
+    }
+
+    /// Replaces the words with `indices`, which must yield `len` values.
+    fn pack(&mut self, indices: impl Iterator<Item = u64>) {
+        self.words = vec![0; self.word_count()];
+        if self.bits == 0 {
+            return;
+        }
+        let per_word = self.per_word();
+        for (i, index) in indices.enumerate() {
+            self.words[i / per_word] |= index << ((i % per_word) as u32 * self.bits);
+        }
+    }
+
+    fn index_at(&self, i: usize) -> u64 {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = self.per_word();
+        (self.words[i / per_word] >> ((i % per_word) as u32 * self.bits)) & self.mask()
+    }
+
+    /// Every palette index in order.
+    fn indices(&self) -> impl Iterator<Item = u64> + '_ {
+        (0..self.len).map(|i| self.index_at(i))
+    }
+
+    pub fn get(&self, i: usize) -> u8 {
+        assert!(i < self.len, "voxel {i} out of bounds");
+        self.palette[self.index_at(i) as usize]
+    }
+
+    pub fn set(&mut self, i: usize, id: u8) {
+        assert!(i < self.len, "voxel {i} out of bounds");
+        let index = match self.palette.iter().position(|p| *p == id) {
+            Some(index) => index,
+            None => {
+                self.palette.push(id);
+                // widen every index once the palette outgrows them
+                let bits = bits_for(self.palette.len());
+                if bits != self.bits {
+                    let old = self.indices().collect::<Vec<_>>();
+                    self.bits = bits;
+                    self.pack(old.into_iter());
+                }
+                self.palette.len() - 1
+            }
+        };
+        if self.bits == 0 {
+            return;
+        }
+
+        let (per_word, mask) = (self.per_word(), self.mask());
+        let shift = (i % per_word) as u32 * self.bits;
+        let word = &mut self.words[i / per_word];
+        *word = (*word & !(mask << shift)) | ((index as u64) << shift);
+    }
+
+    /// Decompresses everything, e.g. for a full texture upload.
+    pub fn unpack(&self) -> Vec<u8> {
+        if self.bits == 0 {
+            return vec![self.palette[0]; self.len];
+        }
+
+        // whole words at a time, this is the hot path of every chunk upload
+        let (bits, mask) = (self.bits, self.mask());
+        let mut data = Vec::with_capacity(self.words.len() * self.per_word());
+        for word in &self.words {
+            let mut word = *word;
+            for _ in 0..self.per_word() {
+                data.push(self.palette[(word & mask) as usize]);
+                word >>= bits;
+            }
+        }
+        data.truncate(self.len);
+        data
+    }
+
+    /// Drops palette entries no voxel uses any more and narrows the indices to match.
+    pub fn compact(&mut self) {
+        *self = Self::from_raw(&self.unpack());
+    }
+}
+
+/// CPU copy of a cubic chunk of block ids, laid out x-major like the `R8Uint` texture
+/// built from it and kept packed until it is uploaded. Edited boxes are remembered
+/// until the next `upload`.
+#[derive(Debug, Clone)]
+pub struct VoxelChunk {
+    size: u32,
+    data: PackedVoxels,
+    dirty: Vec<VoxelRegion>,
+    /// Set by any edit and never cleared by uploads, tells saves which chunks
+    /// can't just be regenerated.
+    modified: bool,
+}
+
+impl VoxelChunk {
+    pub fn new(size: u32) -> Self {
+        Self {
+            size,
+            data: PackedVoxels::uniform(size.pow(3) as usize, 0),
+            dirty: vec![],
+            modified: false,
+        }
+    }
+
+    pub fn from_raw(size: u32, data: Vec<u8>) -> Self {
+        Self::from_packed(size, PackedVoxels::from_raw(&data))
+    }
+
+    pub fn from_packed(size: u32, data: PackedVoxels) -> Self {
+        assert_eq!(
+            data.len(),
+            size.pow(3) as usize,
+            "voxel data has wrong size"
+        );
+        Self {
+            size,
+            data,
+            dirty: vec![],
+            modified: false,
+        }
+    }
+
+    pub fn size(&self) -> u32 {
+        self.size
+    }
+
+    pub fn packed(&self) -> &PackedVoxels {
+        &self.data
+    }
+
+    /// Every block id unpacked, in texture order.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        self.data.unpack()
+    }
+
+    pub fn in_bounds(&self, x: i32, y: i32, z: i32) -> bool {
+        let size = self.size as i32;
+        (0..size).contains(&x) && (0..size).contains(&y) && (0..size).contains(&z)
+    }
+
+    fn index(&self, x: u32, y: u32, z: u32) -> usize {
+        assert!(
+            x < self.size && y < self.size && z < self.size,
+            "voxel ({x}, {y}, {z}) outside chunk of size {}",
+            self.size
+        );
+        (x + y * self.size + z * self.size.pow(2)) as usize
+    }
+
+    pub fn get(&self, x: u32, y: u32, z: u32) -> u8 {
+        self.data.get(self.index(x, y, z))
+    }
+
+    pub fn set(&mut self, x: u32, y: u32, z: u32, id: u8) {
+        let i = self.index(x, y, z);
+        if self.data.get(i) != id {
+            self.data.set(i, id);
+            self.mark_dirty(VoxelRegion::voxel(x, y, z));
+        }
+    }
+
+    pub fn fill(&mut self, id: u8) {
+        self.data = PackedVoxels::uniform(self.data.len(), id);
+        self.mark_dirty(self.bounds());
+    }
+
+    /// Sets every voxel in `region` to `id`.
+    pub fn fill_region(&mut self, region: VoxelRegion, id: u8) {
+        if region == self.bounds() {
+            return self.fill(id);
+        }
+        for z in region.min[2]..region.max[2] {
+            for y in region.min[1]..region.max[1] {
+                for x in region.min[0]..region.max[0] {
+                    let i = self.index(x, y, z);
+                    self.data.set(i, id);
+                }
+            }
+        }
+        self.mark_dirty(region);
+    }
+
+    /// Every voxel with its position, in memory order.
+    pub fn iter(&self) -> impl Iterator<Item = ([u32; 3], u8)> + '_ {
+        let size = self.size;
+        (0..self.data.len()).map(move |i| {
+            let id = self.data.get(i);
+            let i = i as u32;
+            ([i % size, (i / size) % size, i / size.pow(2)], id)
+        })
+    }
+
+    pub fn bounds(&self) -> VoxelRegion {
+        VoxelRegion {
+            min: [0; 3],
+            max: [self.size; 3],
+        }
+    }
+
+    /// Records an edited box, merging it with every pending box it touches.
+    pub fn mark_dirty(&mut self, mut region: VoxelRegion) {
+        // a merge can grow the box into ones it didn't touch before, so repeat
+        while let Some(i) = self.dirty.iter().position(|d| d.touches(&region)) {
+            region = region.union(&self.dirty.swap_remove(i));
+        }
+        self.dirty.push(region);
+        self.modified = true;
+
+        if self.dirty.len() > MAX_DIRTY_REGIONS {
+            let all = self.dirty.drain(..).reduce(|a, b| a.union(&b));
+            self.dirty.extend(all);
+        }
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+
+    pub fn set_modified(&mut self, modified: bool) {
+        self.modified = modified;
+    }
+
+    pub fn is_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Returns the disjoint boxes edited since the last call and forgets them.
+    pub fn take_dirty(&mut self) -> Vec<VoxelRegion> {
+        std::mem::take(&mut self.dirty)
+    }
+
+    /// The voxels of `region` unpacked into a tightly packed x-major block.
+    pub fn region_bytes(&self, region: VoxelRegion) -> Vec<u8> {
+        if region == self.bounds() {
+            return self.to_bytes();
+        }
+        let extent = region.extent();
+        let mut data = Vec::with_capacity(
+            (extent.width * extent.height * extent.depth_or_array_layers) as usize,
+        );
+        for z in region.min[2]..region.max[2] {
+            for y in region.min[1]..region.max[1] {
+                for x in region.min[0]..region.max[0] {
+                    data.push(self.get(x, y, z));
+                }
+            }
+        }
+        data
+    }
+
+    /// Builds the 3D texture the shader reads, clearing any pending edits.
+    pub fn create_texture(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> Texture {
+        let bytes = self.to_bytes();
+        self.create_texture_from(device, queue, &bytes)
+    }
+
+    /// `create_texture` with the voxels already unpacked by `to_bytes`, which is the
+    /// slow part and can be done on another thread.
+    pub fn create_texture_from(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        bytes: &[u8],
+    ) -> Texture {
+        assert_eq!(
+            bytes.len(),
+            self.size.pow(3) as usize,
+            "not the unpacked voxels of this chunk"
+        );
+        let texture =
+            Texture::create_voxel_texture(device, self.bounds().extent(), "voxel_texture");
+        self.dirty.clear();
+        texture.write_voxels(queue, self.bounds(), bytes);
+        texture
+    }
+
+    /// Copies only the boxes edited since the last upload into `texture` and
+    /// returns them.
+    pub fn upload(&mut self, queue: &wgpu::Queue, texture: &Texture) -> Vec<VoxelRegion> {
+        let regions = self.take_dirty();
+        for region in &regions {
+            texture.write_voxels(queue, *region, &self.region_bytes(*region));
+        }
+        regions
+    }
+}
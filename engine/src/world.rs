@@ -0,0 +1,1105 @@
+use crate::{
+    blocks::BlockId,
+    chunk_gen::{self, TerrainGenerator, CHUNK_SIZE},
+    instances::CFrame,
+    irradiance::{BounceLight, Neighbours, ProbeBaker, ProbeGrid, DEFAULT_PROBE_RESOLUTION},
+    light_levels::{Borders, LightLevels, NEIGHBOURS},
+    occupancy::OccupancyMips,
+    save::{self, SaveHeader},
+    texture::Texture,
+    voxel::{VoxelChunk, VoxelRegion, MAX_DIRTY_REGIONS},
+};
+use cgmath::{EuclideanSpace, InnerSpace, Point3, Vector3};
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader, BufWriter},
+    path::Path,
+    sync::Arc,
+};
+use wgpu::util::DeviceExt;
+
+pub type ChunkCoord = cgmath::Vector3<i32>;
+/// Position of a single voxel counted across the whole world.
+pub type VoxelCoord = cgmath::Vector3<i32>;
+
+/// Edge length of a chunk's bounding cube in world units, the cube mesh spans -1..1.
+pub const CHUNK_WORLD_SIZE: f32 = 2.0;
+pub const VOXEL_SIZE: f32 = CHUNK_WORLD_SIZE / CHUNK_SIZE as f32;
+
+pub struct Chunk {
+    pub cframe: CFrame,
+    pub voxels: VoxelChunk,
+    pub texture: Texture,
+    /// Coarse occupancy of `voxels`, kept up to date by `World::upload_dirty`.
+    pub occupancy: OccupancyMips,
+    pub occupancy_texture: Texture,
+    /// Bounced light around the chunk, baked by a `LightJob` and touched up around
+    /// edits by the next one. Left unbaked where `World::probes_on_gpu`, the probes
+    /// only live in `probe_texture` then.
+    pub probes: ProbeGrid,
+    pub probe_texture: Texture,
+    /// Sky and block light of every voxel, flood filled and touched up the same way
+    /// as `probes`.
+    pub light_levels: LightLevels,
+    pub light_texture: Texture,
+    bind_group: wgpu::BindGroup,
+    /// Where the chunk's `cframe` is in the instance buffer, `None` until the next
+    /// `update_instance_buffer` after it was inserted.
+    instance: Option<u32>,
+    /// What the lighting was last worked out with, `None` until it first was.
+    lit_with: Option<BounceLight>,
+    /// What the probes were last baked with, `None` until they first were.
+    probes_lit_with: Option<BounceLight>,
+    /// Changes whenever lighting worked out before goes stale, so results of a
+    /// `LightJob` started earlier can be told apart. Unique across the world.
+    version: u64,
+    /// `version` of the `LightJob` out for the chunk, if there is one.
+    job: Option<u64>,
+    /// A neighbour's light along one of the sides changed since the light levels
+    /// were flood filled.
+    borders_changed: bool,
+    /// Boxes edited since the lighting was last worked out, for the next `LightJob`
+    /// to relight around.
+    edits: Vec<VoxelRegion>,
+    /// Boxes to relight the probes around: the edits, and boxes along the sides
+    /// where the probes see the neighbour there differently since they were baked.
+    probe_edits: Vec<VoxelRegion>,
+    /// A copy of `voxels` to hand to `LightJob`s, made when the first one needs it
+    /// and dropped on the next edit.
+    snapshot: Option<Arc<VoxelChunk>>,
+}
+
+impl Chunk {
+    /// Light levels only depend on the voxels and what the blocks give off and let
+    /// through, a new sun leaves them alone.
+    fn levels_stale(&self, light: &BounceLight) -> bool {
+        !self.light_levels.is_built()
+            || self.borders_changed
+            || self.lit_with.as_ref().map(BounceLight::block_light) != Some(light.block_light())
+    }
+
+    fn probes_stale(&self, light: &BounceLight) -> bool {
+        self.probes_lit_with.as_ref() != Some(light)
+    }
+
+    fn snapshot(&mut self) -> Arc<VoxelChunk> {
+        self.snapshot
+            .get_or_insert_with(|| Arc::new(self.voxels.clone()))
+            .clone()
+    }
+
+    fn add_edits(&mut self, regions: impl IntoIterator<Item = VoxelRegion>) {
+        add_regions(&mut self.edits, regions);
+    }
+}
+
+/// Past `MAX_DIRTY_REGIONS` the boxes are merged into one, like the voxel uploads.
+fn add_regions(list: &mut Vec<VoxelRegion>, regions: impl IntoIterator<Item = VoxelRegion>) {
+    list.extend(regions);
+    if list.len() > MAX_DIRTY_REGIONS {
+        let all = list.drain(..).reduce(|a, b| a.union(&b));
+        list.extend(all);
+    }
+}
+
+/// The layer of voxels along `side` of a chunk.
+fn layer(size: u32, side: usize) -> VoxelRegion {
+    let offset = NEIGHBOURS[side];
+    let axis = offset.iter().position(|&v| v != 0).unwrap();
+    let mut layer = VoxelRegion {
+        min: [0; 3],
+        max: [size; 3],
+    };
+    if offset[axis] > 0 {
+        layer.min[axis] = size - 1;
+    } else {
+        layer.max[axis] = 1;
+    }
+    layer
+}
+
+/// Lighting worked out for a chunk away from the render thread. Parts that were
+/// still up to date are left out.
+pub struct ChunkLight {
+    pub light_levels: Option<LightLevels>,
+    pub probes: Option<ProbeGrid>,
+    /// What it was worked out with.
+    pub light: BounceLight,
+}
+
+impl ChunkLight {
+    /// Lights `voxels` from scratch with what shines in from the neighbours, see
+    /// `World::borders`, and what the probes see of them, see
+    /// `World::neighbour_voxels`. This takes a while, `ChunkStreamer` does it on
+    /// the blocking pool right after generating the chunk. Without a
+    /// `probe_resolution` the probes are left to `World::bake_probes`.
+    pub fn bake(
+        voxels: &VoxelChunk,
+        borders: &Borders,
+        neighbours: &Neighbours,
+        light: &BounceLight,
+        probe_resolution: Option<u32>,
+    ) -> Self {
+        let mut light_levels = LightLevels::new(voxels.size());
+        for (side, face) in borders.iter().enumerate() {
+            if let Some(face) = face {
+                light_levels.set_border(side, face);
+            }
+        }
+        light_levels.update(voxels, voxels.bounds(), light.block_light());
+        let probes = probe_resolution.map(|resolution| {
+            let mut probes = ProbeGrid::new(resolution);
+            probes.update_regions(voxels, neighbours, &[voxels.bounds()], light);
+            probes
+        });
+        Self {
+            light_levels: Some(light_levels),
+            probes,
+            light: light.clone(),
+        }
+    }
+}
+
+/// Everything about a new chunk that takes a while to work out, so
+/// `World::insert_chunk` is left with creating and filling its textures.
+/// `ChunkStreamer` makes these on the blocking pool.
+pub struct NewChunk {
+    voxels: VoxelChunk,
+    /// `voxels` unpacked for the texture.
+    bytes: Vec<u8>,
+    occupancy: OccupancyMips,
+    light: Option<ChunkLight>,
+    /// Which neighbours the probes were baked with.
+    seen: [bool; 6],
+}
+
+impl NewChunk {
+    /// Left unlit for a `LightJob`.
+    pub fn new(voxels: VoxelChunk) -> Self {
+        Self {
+            bytes: voxels.to_bytes(),
+            occupancy: OccupancyMips::build(&voxels),
+            voxels,
+            light: None,
+            seen: [false; 6],
+        }
+    }
+
+    /// Lit from scratch right away, see `ChunkLight::bake`.
+    pub fn lit(
+        voxels: VoxelChunk,
+        borders: &Borders,
+        neighbours: &Neighbours,
+        light: &BounceLight,
+        probe_resolution: Option<u32>,
+    ) -> Self {
+        let light = ChunkLight::bake(&voxels, borders, neighbours, light, probe_resolution);
+        Self {
+            light: Some(light),
+            seen: neighbours.each_ref().map(Option::is_some),
+            ..Self::new(voxels)
+        }
+    }
+
+    pub fn voxels(&self) -> &VoxelChunk {
+        &self.voxels
+    }
+}
+
+/// A loaded chunk whose lighting went stale, with a copy of everything needed to
+/// redo it off the render thread. Comes from `World::next_light_job` and goes back
+/// through `World::finish_light_job`.
+pub struct LightJob {
+    coord: ChunkCoord,
+    version: u64,
+    voxels: Arc<VoxelChunk>,
+    /// What the probes see around the chunk, only filled in if they're relit.
+    neighbours: Neighbours,
+    light: BounceLight,
+    /// Light levels with up to date borders and the boxes to flood fill them again
+    /// around: the whole chunk if they went stale, or the edits since. `None` if
+    /// they're up to date.
+    light_levels: Option<(LightLevels, Vec<VoxelRegion>)>,
+    /// Probes to relight and the boxes to relight them around, the same way but
+    /// starting from a fresh grid when they went stale.
+    probes: Option<(ProbeGrid, Vec<VoxelRegion>)>,
+    /// The chunk's edits the job took on, handed back if it comes to nothing.
+    edits: Vec<VoxelRegion>,
+    probe_edits: Vec<VoxelRegion>,
+}
+
+/// What a `LightJob` worked out.
+pub struct Relit {
+    coord: ChunkCoord,
+    version: u64,
+    light: ChunkLight,
+    /// Voxels whose light levels changed.
+    levels_relit: Vec<VoxelRegion>,
+    /// Probes that changed.
+    probes_relit: Vec<VoxelRegion>,
+    edits: Vec<VoxelRegion>,
+    probe_edits: Vec<VoxelRegion>,
+}
+
+impl LightJob {
+    pub fn coord(&self) -> ChunkCoord {
+        self.coord
+    }
+
+    /// Redoes the stale parts, this takes a while.
+    pub fn run(self) -> Relit {
+        let (light_levels, levels_relit) = match self.light_levels {
+            Some((mut levels, regions)) => {
+                let relit = levels.update_regions(&self.voxels, &regions, self.light.block_light());
+                (Some(levels), relit)
+            }
+            None => (None, vec![]),
+        };
+        let (probes, probes_relit) = match self.probes {
+            Some((mut probes, regions)) => {
+                let relit =
+                    probes.update_regions(&self.voxels, &self.neighbours, &regions, &self.light);
+                (Some(probes), relit)
+            }
+            None => (None, vec![]),
+        };
+        Relit {
+            coord: self.coord,
+            version: self.version,
+            light: ChunkLight {
+                light_levels,
+                probes,
+                light: self.light,
+            },
+            levels_relit,
+            probes_relit,
+            edits: self.edits,
+            probe_edits: self.probe_edits,
+        }
+    }
+}
+
+/// Sparse set of chunks, each with its own voxel volume and bounding cube instance.
+pub struct World {
+    chunks: HashMap<ChunkCoord, Chunk>,
+    /// Edited chunks that aren't loaded right now, kept so the edits survive
+    /// until the chunk streams back in or the world is saved.
+    stored: HashMap<ChunkCoord, VoxelChunk>,
+    generator: Arc<dyn TerrainGenerator>,
+    bind_group_layout: wgpu::BindGroupLayout,
+    /// Voxels of an all-air chunk, bound in place of neighbours that aren't loaded.
+    air: Texture,
+    instance_buffer: wgpu::Buffer,
+    /// Chunks were inserted since the instance buffer was last built.
+    instances_changed: bool,
+    probe_resolution: u32,
+    /// Bakes the probes in a compute pass, `None` where they're left to `LightJob`s.
+    probe_baker: Option<ProbeBaker>,
+    /// What chunks are lit with, from the last `upload_dirty`.
+    bounce_light: Option<BounceLight>,
+    /// Last `Chunk::version` handed out.
+    versions: u64,
+}
+
+pub fn chunk_origin(coord: ChunkCoord) -> cgmath::Vector3<f32> {
+    coord.cast::<f32>().unwrap() * CHUNK_WORLD_SIZE
+}
+
+/// World position in voxel units, voxel `v` covers `v..v + 1` on each axis.
+pub fn to_voxel_space(pos: Point3<f32>) -> Vector3<f32> {
+    (pos.to_vec() + Vector3::new(1.0, 1.0, 1.0) * CHUNK_WORLD_SIZE / 2.0) / VOXEL_SIZE
+}
+
+/// Splits a world voxel position into its chunk and the position inside it.
+pub fn split_voxel(voxel: VoxelCoord) -> (ChunkCoord, [u32; 3]) {
+    let size = CHUNK_SIZE as i32;
+    (
+        voxel.map(|v| v.div_euclid(size)),
+        [voxel.x, voxel.y, voxel.z].map(|v| v.rem_euclid(size) as u32),
+    )
+}
+
+impl World {
+    pub fn new(device: &wgpu::Device, generator: Arc<dyn TerrainGenerator>) -> Self {
+        let volume = |binding, sample_type| wgpu::BindGroupLayoutEntry {
+            binding,
+            visibility: wgpu::ShaderStages::FRAGMENT,
+            ty: wgpu::BindingType::Texture {
+                multisampled: false,
+                view_dimension: wgpu::TextureViewDimension::D3,
+                sample_type,
+            },
+            count: None,
+        };
+        let bind_group_layout = device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            entries: &[
+                volume(0, wgpu::TextureSampleType::Uint),
+                volume(1, wgpu::TextureSampleType::Uint),
+                volume(2, wgpu::TextureSampleType::Float { filterable: true }),
+                wgpu::BindGroupLayoutEntry {
+                    binding: 3,
+                    visibility: wgpu::ShaderStages::FRAGMENT,
+                    ty: wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering),
+                    count: None,
+                },
+                volume(4, wgpu::TextureSampleType::Uint),
+                // the voxels of the chunks around, in `NEIGHBOURS` order
+                volume(5, wgpu::TextureSampleType::Uint),
+                volume(6, wgpu::TextureSampleType::Uint),
+                volume(7, wgpu::TextureSampleType::Uint),
+                volume(8, wgpu::TextureSampleType::Uint),
+                volume(9, wgpu::TextureSampleType::Uint),
+                volume(10, wgpu::TextureSampleType::Uint),
+            ],
+            label: Some("chunk_bind_group_layout"),
+        });
+
+        let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Chunk Instance Buffer"),
+            contents: bytemuck::cast_slice(&Vec::<u8>::new()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        // new textures start out zeroed, which is all air
+        let air = Texture::create_voxel_texture(
+            device,
+            wgpu::Extent3d {
+                width: CHUNK_SIZE,
+                height: CHUNK_SIZE,
+                depth_or_array_layers: CHUNK_SIZE,
+            },
+            "air_texture",
+        );
+
+        Self {
+            chunks: HashMap::new(),
+            stored: HashMap::new(),
+            generator,
+            bind_group_layout,
+            air,
+            instance_buffer,
+            instances_changed: false,
+            probe_resolution: DEFAULT_PROBE_RESOLUTION,
+            probe_baker: ProbeBaker::new(device),
+            bounce_light: None,
+            versions: 0,
+        }
+    }
+
+    pub fn bind_group_layout(&self) -> &wgpu::BindGroupLayout {
+        &self.bind_group_layout
+    }
+
+    pub fn generator(&self) -> &Arc<dyn TerrainGenerator> {
+        &self.generator
+    }
+
+    pub fn probe_resolution(&self) -> u32 {
+        self.probe_resolution
+    }
+
+    /// Whether the probes are baked in a compute pass by `bake_probes`, rather than
+    /// by `LightJob`s.
+    pub fn probes_on_gpu(&self) -> bool {
+        self.probe_baker.is_some()
+    }
+
+    /// Leaves the probes to `LightJob`s from now on, even where the device could
+    /// bake them in a compute pass. Every chunk's probes are baked again.
+    pub fn bake_probes_on_cpu(&mut self) {
+        self.probe_baker = None;
+        for chunk in self.chunks.values_mut() {
+            chunk.probes_lit_with = None;
+        }
+    }
+
+    /// What chunks are lit with, `None` before the first `upload_dirty`.
+    pub fn bounce_light(&self) -> Option<&BounceLight> {
+        self.bounce_light.as_ref()
+    }
+
+    fn next_version(versions: &mut u64) -> u64 {
+        *versions += 1;
+        *versions
+    }
+
+    /// Changes how many irradiance probes each chunk gets along every axis. All
+    /// loaded chunks go dark until a `LightJob` or `bake_probes` rebakes them.
+    pub fn set_probe_resolution(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        resolution: u32,
+    ) {
+        assert!(
+            (1..=256).contains(&resolution),
+            "probe resolution must be between 1 and 256"
+        );
+        self.probe_resolution = resolution;
+        for chunk in self.chunks.values_mut() {
+            chunk.version = Self::next_version(&mut self.versions);
+            chunk.probes = ProbeGrid::new(resolution);
+            chunk.probes_lit_with = None;
+            chunk.probe_edits.clear();
+            chunk.probe_texture = chunk.probes.create_texture(device, queue);
+        }
+        let coords = self.chunks.keys().copied().collect::<Vec<_>>();
+        for coord in coords {
+            self.rebind(device, coord);
+        }
+    }
+
+    /// The voxel textures of the chunks around `coord` for its bind group, with
+    /// `air` for any that isn't loaded or doesn't match the chunk's `size`.
+    fn neighbour_textures(&self, coord: ChunkCoord, size: u32) -> [&Texture; 6] {
+        std::array::from_fn(|side| {
+            self.chunks
+                .get(&(coord + ChunkCoord::from(NEIGHBOURS[side])))
+                .filter(|neighbour| neighbour.voxels.size() == size)
+                .map_or(&self.air, |neighbour| &neighbour.texture)
+        })
+    }
+
+    /// Builds the bind group of the chunk at `coord` again, after its textures or
+    /// the chunks around it changed.
+    fn rebind(&mut self, device: &wgpu::Device, coord: ChunkCoord) {
+        let Some(chunk) = self.chunks.get(&coord) else {
+            return;
+        };
+        let bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            [
+                &chunk.texture,
+                &chunk.occupancy_texture,
+                &chunk.probe_texture,
+                &chunk.light_texture,
+            ],
+            self.neighbour_textures(coord, chunk.voxels.size()),
+        );
+        self.chunks.get_mut(&coord).unwrap().bind_group = bind_group;
+    }
+
+    fn create_bind_group(
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+        [voxels, occupancy, probes, light]: [&Texture; 4],
+        neighbours: [&Texture; 6],
+    ) -> wgpu::BindGroup {
+        let [down, up, west, east, north, south] =
+            neighbours.map(|neighbour| wgpu::BindingResource::TextureView(&neighbour.view));
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&voxels.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::TextureView(&occupancy.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 2,
+                    resource: wgpu::BindingResource::TextureView(&probes.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 3,
+                    resource: wgpu::BindingResource::Sampler(&probes.sampler),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 4,
+                    resource: wgpu::BindingResource::TextureView(&light.view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 5,
+                    resource: down,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 6,
+                    resource: up,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 7,
+                    resource: west,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 8,
+                    resource: east,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 9,
+                    resource: north,
+                },
+                wgpu::BindGroupEntry {
+                    binding: 10,
+                    resource: south,
+                },
+            ],
+            label: Some("chunk_bind_group"),
+        })
+    }
+
+    /// Generates a chunk on the spot. It's left unlit for a `LightJob`, see
+    /// `finish_lighting`.
+    pub fn generate_chunk(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        coord: ChunkCoord,
+    ) {
+        let chunk = NewChunk::new(self.generator.generate(coord));
+        self.insert_chunk(device, queue, coord, chunk);
+        self.update_instance_buffer(device);
+    }
+
+    /// Loads a chunk from the stored edits instead of generating it, returns false
+    /// if there is nothing stored for `coord`. Unlit just like `generate_chunk`.
+    pub fn restore_chunk(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        coord: ChunkCoord,
+    ) -> bool {
+        match self.stored.remove(&coord) {
+            Some(voxels) => {
+                self.insert_chunk(device, queue, coord, NewChunk::new(voxels));
+                self.update_instance_buffer(device);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn is_stored(&self, coord: ChunkCoord) -> bool {
+        self.stored.contains_key(&coord)
+    }
+
+    /// The stored edits for a chunk that isn't loaded, see `restore_chunk`.
+    pub fn stored_chunk(&self, coord: ChunkCoord) -> Option<&VoxelChunk> {
+        self.stored.get(&coord)
+    }
+
+    /// Uploads a chunk and adds it, replacing any previous one and any stored edits
+    /// for it. Without light, or where it's out of date, the chunk waits for a
+    /// `LightJob`. It isn't drawn before the next `update_instance_buffer`.
+    pub fn insert_chunk(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        coord: ChunkCoord,
+        chunk: NewChunk,
+    ) {
+        let NewChunk {
+            mut voxels,
+            bytes,
+            occupancy,
+            light,
+            seen,
+        } = chunk;
+        self.stored.remove(&coord);
+        let texture = voxels.create_texture_from(device, queue, &bytes);
+        drop(bytes);
+        let occupancy_texture = occupancy.create_texture(device, queue);
+        let (light_levels, probes, lit_with) = match light {
+            Some(ChunkLight {
+                light_levels,
+                probes,
+                light,
+            }) => (light_levels, probes, Some(light)),
+            None => (None, None, None),
+        };
+        // baked before `set_probe_resolution` changed it, or not at all
+        let probes = probes.filter(|probes| probes.resolution() == self.probe_resolution);
+        let probes_lit_with = probes.as_ref().and(lit_with.clone());
+        let probes = probes.unwrap_or_else(|| ProbeGrid::new(self.probe_resolution));
+        let probe_texture = probes.create_texture(device, queue);
+        let mut light_levels = light_levels.unwrap_or_else(|| LightLevels::new(voxels.size()));
+        // the neighbours may have changed since the light was worked out
+        let mut borders_changed = false;
+        for (side, face) in self.borders(coord).iter().enumerate() {
+            if let Some(face) = face {
+                borders_changed |= light_levels.set_border(side, face);
+            }
+        }
+        let light_texture = light_levels.create_texture(device, queue);
+        let bind_group = Self::create_bind_group(
+            device,
+            &self.bind_group_layout,
+            [&texture, &occupancy_texture, &probe_texture, &light_texture],
+            self.neighbour_textures(coord, voxels.size()),
+        );
+
+        let cframe = CFrame {
+            position: chunk_origin(coord),
+            ..Default::default()
+        };
+
+        self.chunks.insert(
+            coord,
+            Chunk {
+                cframe,
+                voxels,
+                texture,
+                occupancy,
+                occupancy_texture,
+                probes,
+                probe_texture,
+                light_levels,
+                light_texture,
+                bind_group,
+                instance: None,
+                lit_with,
+                probes_lit_with,
+                version: Self::next_version(&mut self.versions),
+                job: None,
+                borders_changed,
+                edits: vec![],
+                probe_edits: vec![],
+                snapshot: None,
+            },
+        );
+        self.share_borders(coord);
+
+        // probes along the sides shared with chunks that weren't there before have
+        // terrain to see now, on either side, and the neighbours' rays carry on
+        // into the new voxels
+        for side in 0..NEIGHBOURS.len() {
+            let neighbour = coord + ChunkCoord::from(NEIGHBOURS[side]);
+            self.rebind(device, neighbour);
+            if let Some(neighbour) = self.chunks.get_mut(&neighbour) {
+                add_regions(&mut neighbour.probe_edits, [layer(CHUNK_SIZE, side ^ 1)]);
+                if !seen[side] {
+                    let chunk = self.chunks.get_mut(&coord).unwrap();
+                    add_regions(&mut chunk.probe_edits, [layer(CHUNK_SIZE, side)]);
+                }
+            }
+        }
+        self.instances_changed = true;
+    }
+
+    /// Copies of the voxels of the chunks around `coord`, for the rays of a probe
+    /// bake that leave it.
+    pub fn neighbour_voxels(&mut self, coord: ChunkCoord) -> Neighbours {
+        std::array::from_fn(|side| {
+            let neighbour = coord + ChunkCoord::from(NEIGHBOURS[side]);
+            self.chunks.get_mut(&neighbour).map(Chunk::snapshot)
+        })
+    }
+
+    /// What shines into the chunk at `coord` from the lit chunks around it.
+    pub fn borders(&self, coord: ChunkCoord) -> Borders {
+        std::array::from_fn(|side| {
+            let neighbour = self
+                .chunks
+                .get(&(coord + ChunkCoord::from(NEIGHBOURS[side])))?;
+            let levels = &neighbour.light_levels;
+            levels.is_built().then(|| levels.face(side ^ 1))
+        })
+    }
+
+    /// Hands the light along the sides of a chunk to its neighbours, which go stale
+    /// wherever that changes what shines into them. Unloading a chunk leaves the
+    /// last light it handed out in place.
+    fn share_borders(&mut self, coord: ChunkCoord) {
+        let Some(chunk) = self.chunks.get(&coord) else {
+            return;
+        };
+        if !chunk.light_levels.is_built() {
+            return;
+        }
+        let faces: [Vec<u8>; 6] = std::array::from_fn(|side| chunk.light_levels.face(side));
+        for (side, face) in faces.iter().enumerate() {
+            let Some(neighbour) = self
+                .chunks
+                .get_mut(&(coord + ChunkCoord::from(NEIGHBOURS[side])))
+            else {
+                continue;
+            };
+            if neighbour.light_levels.set_border(side ^ 1, face) {
+                neighbour.borders_changed = true;
+                neighbour.version = Self::next_version(&mut self.versions);
+            }
+        }
+    }
+
+    /// Unloads a chunk, edited voxels are kept around for `restore_chunk`. The
+    /// instance buffer keeps its entry until the next `update_instance_buffer`.
+    pub fn remove_chunk(&mut self, device: &wgpu::Device, coord: ChunkCoord) {
+        let Some(chunk) = self.chunks.remove(&coord) else {
+            return;
+        };
+        if chunk.voxels.is_modified() {
+            self.stored.insert(coord, chunk.voxels);
+        }
+        for offset in NEIGHBOURS {
+            self.rebind(device, coord + ChunkCoord::from(offset));
+        }
+    }
+
+    pub fn get_chunk(&self, coord: ChunkCoord) -> Option<&Chunk> {
+        self.chunks.get(&coord)
+    }
+
+    pub fn get_chunk_mut(&mut self, coord: ChunkCoord) -> Option<&mut Chunk> {
+        self.chunks.get_mut(&coord)
+    }
+
+    /// The block at a world voxel position, `None` if its chunk isn't loaded.
+    pub fn get_voxel(&self, voxel: VoxelCoord) -> Option<BlockId> {
+        let (chunk, [x, y, z]) = split_voxel(voxel);
+        self.chunks.get(&chunk).map(|c| c.voxels.get(x, y, z))
+    }
+
+    /// Changes a voxel, returns false if its chunk isn't loaded. The texture is
+    /// updated on the next `upload_dirty`.
+    pub fn set_voxel(&mut self, voxel: VoxelCoord, id: BlockId) -> bool {
+        let (chunk, [x, y, z]) = split_voxel(voxel);
+        match self.chunks.get_mut(&chunk) {
+            Some(chunk) => {
+                chunk.voxels.set(x, y, z, id);
+                chunk.snapshot = None;
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn contains_chunk(&self, coord: ChunkCoord) -> bool {
+        self.chunks.contains_key(&coord)
+    }
+
+    pub fn chunk_coords(&self) -> impl Iterator<Item = &ChunkCoord> {
+        self.chunks.keys()
+    }
+
+    pub fn len(&self) -> usize {
+        self.chunks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty()
+    }
+
+    /// Writes every edited chunk, loaded or not, to `path`. Untouched chunks are
+    /// left out since the seed and generator id bring them back. The save goes to a
+    /// temporary file next to `path` first and replaces it only once complete, so a
+    /// crash halfway through leaves the previous save intact.
+    pub fn save(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        let header = SaveHeader {
+            version: save::VERSION,
+            seed: self.generator.seed(),
+            generator: self.generator.id(),
+            chunk_size: CHUNK_SIZE,
+        };
+        let loaded = self
+            .chunks
+            .iter()
+            .map(|(coord, chunk)| (*coord, &chunk.voxels));
+        let chunks = loaded
+            .chain(self.stored.iter().map(|(coord, voxels)| (*coord, voxels)))
+            .filter(|(_, voxels)| voxels.is_modified())
+            .collect::<Vec<_>>();
+
+        let path = path.as_ref();
+        let mut tmp_name = path.file_name().unwrap_or_default().to_owned();
+        tmp_name.push(".tmp");
+        let tmp = path.with_file_name(tmp_name);
+
+        let written = File::create(&tmp).and_then(|file| {
+            let mut writer = BufWriter::new(file);
+            save::write(&mut writer, &header, chunks.into_iter())?;
+            writer
+                .into_inner()
+                .map_err(|err| err.into_error())?
+                .sync_all()
+        });
+        if let Err(err) = written {
+            let _ = std::fs::remove_file(&tmp);
+            return Err(err);
+        }
+        std::fs::rename(&tmp, path)
+    }
+
+    /// Replaces the world with a save. Every chunk is unloaded and the saved ones
+    /// come back through `restore_chunk` as they are streamed in.
+    pub fn load(&mut self, path: impl AsRef<Path>) -> io::Result<()> {
+        let file = save::read(BufReader::new(File::open(path)?))?;
+        let SaveHeader {
+            seed,
+            generator,
+            chunk_size,
+            ..
+        } = file.header;
+
+        if chunk_size != CHUNK_SIZE {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("save has {chunk_size}³ chunks, expected {CHUNK_SIZE}³"),
+            ));
+        }
+        // keep the current generator if it matches, rebuilding a file one reloads it
+        if generator != self.generator.id() || seed != self.generator.seed() {
+            self.generator = chunk_gen::from_name(&generator, seed).map_err(|err| {
+                io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("can't restore terrain generator {generator:?}: {err}"),
+                )
+            })?;
+        }
+
+        self.chunks.clear();
+        self.stored = file.chunks.into_iter().collect();
+        Ok(())
+    }
+
+    /// Pushes voxel edits made since the last call to the GPU. The lighting around
+    /// them, and of chunks lit with anything but `light`, is only marked stale, see
+    /// `next_light_job`.
+    pub fn upload_dirty(&mut self, queue: &wgpu::Queue, light: &BounceLight) {
+        if self.bounce_light.as_ref() != Some(light) {
+            self.bounce_light = Some(light.clone());
+            if let Some(baker) = &self.probe_baker {
+                baker.set_light(queue, light);
+            }
+        }
+
+        let mut seen_across = vec![];
+        for (coord, chunk) in &mut self.chunks {
+            let regions = chunk.voxels.upload(queue, &chunk.texture);
+            if regions.is_empty() {
+                continue;
+            }
+            chunk.version = Self::next_version(&mut self.versions);
+            chunk.snapshot = None;
+            for &region in &regions {
+                let cells = chunk.occupancy.update(&chunk.voxels, region);
+                chunk
+                    .occupancy
+                    .upload(queue, &chunk.occupancy_texture, &cells);
+                for (side, offset) in NEIGHBOURS.into_iter().enumerate() {
+                    if let Some(seen) = ProbeGrid::seen_across(CHUNK_SIZE, region, side) {
+                        seen_across.push((coord + ChunkCoord::from(offset), seen));
+                    }
+                }
+            }
+            add_regions(&mut chunk.probe_edits, regions.iter().copied());
+            chunk.add_edits(regions);
+        }
+        for (coord, seen) in seen_across {
+            if let Some(chunk) = self.chunks.get_mut(&coord) {
+                add_regions(&mut chunk.probe_edits, [seen]);
+            }
+        }
+    }
+
+    /// Picks the chunk nearest `near` whose lighting is stale and that has no job
+    /// out yet. Nothing is stale before the first `upload_dirty`. The probes are
+    /// only part of the job without `probes_on_gpu`.
+    pub fn next_light_job(&mut self, near: Point3<f32>) -> Option<LightJob> {
+        let light = self.bounce_light.clone()?;
+        let cpu_probes = self.probe_baker.is_none();
+        let probes_stale = |chunk: &Chunk| {
+            cpu_probes && (chunk.probes_stale(&light) || !chunk.probe_edits.is_empty())
+        };
+        let coord = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| {
+                chunk.job.is_none()
+                    && (chunk.levels_stale(&light)
+                        || !chunk.edits.is_empty()
+                        || probes_stale(chunk))
+            })
+            .min_by(|(_, a), (_, b)| {
+                let dis_a = (a.cframe.position - near.to_vec()).magnitude2();
+                let dis_b = (b.cframe.position - near.to_vec()).magnitude2();
+                dis_a.total_cmp(&dis_b)
+            })
+            .map(|(coord, _)| *coord)?;
+
+        let relight_probes = probes_stale(&self.chunks[&coord]);
+        let neighbours = if relight_probes {
+            self.neighbour_voxels(coord)
+        } else {
+            Neighbours::default()
+        };
+
+        let chunk = self.chunks.get_mut(&coord).unwrap();
+        chunk.job = Some(chunk.version);
+        let edits = std::mem::take(&mut chunk.edits);
+        let probe_edits = if relight_probes {
+            std::mem::take(&mut chunk.probe_edits)
+        } else {
+            vec![]
+        };
+        let bounds = chunk.voxels.bounds();
+        let light_levels = if chunk.levels_stale(&light) {
+            Some((chunk.light_levels.clone(), vec![bounds]))
+        } else if !edits.is_empty() {
+            Some((chunk.light_levels.clone(), edits.clone()))
+        } else {
+            None
+        };
+        let probes = if !relight_probes {
+            None
+        } else if chunk.probes_stale(&light) {
+            Some((ProbeGrid::new(self.probe_resolution), vec![bounds]))
+        } else {
+            Some((chunk.probes.clone(), probe_edits.clone()))
+        };
+        Some(LightJob {
+            coord,
+            version: chunk.version,
+            voxels: chunk.snapshot(),
+            neighbours,
+            light,
+            light_levels,
+            probes,
+            edits,
+            probe_edits,
+        })
+    }
+
+    /// Uploads what a `LightJob` worked out, unless the chunk changed or went away
+    /// since the job was handed out. The edits it was to relight around then go
+    /// to the next one.
+    pub fn finish_light_job(&mut self, queue: &wgpu::Queue, relit: Relit) {
+        let Some(chunk) = self.chunks.get_mut(&relit.coord) else {
+            return;
+        };
+        if chunk.job == Some(relit.version) {
+            chunk.job = None;
+        }
+        if chunk.version != relit.version {
+            chunk.add_edits(relit.edits);
+            add_regions(&mut chunk.probe_edits, relit.probe_edits);
+            return;
+        }
+
+        let relit_levels = relit.light.light_levels.is_some();
+        if let Some(levels) = relit.light.light_levels {
+            chunk.light_levels = levels;
+            chunk.borders_changed = false;
+            for region in relit.levels_relit {
+                chunk
+                    .light_levels
+                    .upload(queue, &chunk.light_texture, region);
+            }
+        }
+        if let Some(probes) = relit.light.probes {
+            chunk.probes = probes;
+            for region in relit.probes_relit {
+                chunk.probes.upload(queue, &chunk.probe_texture, region);
+            }
+            chunk.probes_lit_with = Some(relit.light.light.clone());
+        }
+        chunk.lit_with = Some(relit.light.light);
+        if relit_levels {
+            self.share_borders(relit.coord);
+        }
+    }
+
+    /// Bakes the stale probes of up to `max_chunks` chunks nearest `near` in a
+    /// compute pass and returns how many it did. Does nothing without
+    /// `probes_on_gpu` or before the first `upload_dirty`.
+    pub fn bake_probes(
+        &mut self,
+        device: &wgpu::Device,
+        queue: &wgpu::Queue,
+        near: Point3<f32>,
+        max_chunks: usize,
+    ) -> usize {
+        let (Some(baker), Some(light)) = (&self.probe_baker, self.bounce_light.clone()) else {
+            return 0;
+        };
+        let mut stale = self
+            .chunks
+            .iter()
+            .filter(|(_, chunk)| chunk.probes_stale(&light) || !chunk.probe_edits.is_empty())
+            .map(|(coord, chunk)| {
+                let distance = (chunk.cframe.position - near.to_vec()).magnitude2();
+                (*coord, distance)
+            })
+            .collect::<Vec<_>>();
+        stale.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        stale.truncate(max_chunks);
+
+        for &(coord, _) in &stale {
+            let chunk = &self.chunks[&coord];
+            let regions = if chunk.probes_stale(&light) {
+                vec![chunk.voxels.bounds()]
+            } else {
+                chunk.probe_edits.clone()
+            };
+            baker.bake(
+                device,
+                queue,
+                &chunk.texture,
+                self.neighbour_textures(coord, chunk.voxels.size()),
+                &chunk.probe_texture,
+                &regions,
+            );
+            let chunk = self.chunks.get_mut(&coord).unwrap();
+            chunk.probe_edits.clear();
+            chunk.probes_lit_with = Some(light.clone());
+        }
+        stale.len()
+    }
+
+    /// Runs every stale chunk's `LightJob` right here and bakes all the stale
+    /// probes, for when there's no `ChunkStreamer` doing it in the background.
+    pub fn finish_lighting(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) {
+        while let Some(job) = self.next_light_job(Point3::origin()) {
+            let relit = job.run();
+            self.finish_light_job(queue, relit);
+        }
+        self.bake_probes(device, queue, Point3::origin(), usize::MAX);
+    }
+
+    /// Rebuilds the instance buffer if chunks were inserted since it was last built,
+    /// so the new ones get drawn. `ChunkStreamer` does it once per update rather than
+    /// once per chunk.
+    pub fn update_instance_buffer(&mut self, device: &wgpu::Device) {
+        if !self.instances_changed {
+            return;
+        }
+        self.instances_changed = false;
+        let mut instance_data = Vec::with_capacity(self.chunks.len());
+        for chunk in self.chunks.values_mut() {
+            chunk.instance = Some(instance_data.len() as u32);
+            instance_data.push(chunk.cframe.to_raw());
+        }
+
+        self.instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some("Chunk Instance Buffer"),
+            contents: bytemuck::cast_slice(&instance_data),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+    }
+
+    /// Draws every chunk's bounding cube back to front, so the translucent misses of
+    /// nearer chunks blend over the ones behind them.
+    pub(crate) fn draw<'a>(
+        &'a self,
+        render_pass: &mut wgpu::RenderPass<'a>,
+        index_count: u32,
+        eye: Point3<f32>,
+    ) {
+        let mut chunks = self.chunks.values().collect::<Vec<_>>();
+        chunks.sort_by(|a, b| {
+            let dis_a = (a.cframe.position - eye.to_vec()).magnitude2();
+            let dis_b = (b.cframe.position - eye.to_vec()).magnitude2();
+            dis_b.total_cmp(&dis_a)
+        });
+
+        render_pass.set_vertex_buffer(1, self.instance_buffer.slice(..));
+        for chunk in chunks {
+            let Some(instance) = chunk.instance else {
+                continue;
+            };
+            render_pass.set_bind_group(2, &chunk.bind_group, &[]);
+            render_pass.draw_indexed(0..index_count, 0, instance..instance + 1);
+        }
+    }
+}
@@ -0,0 +1,62 @@
+//! Ambient occlusion across chunk borders, rendered on a software adapter. Fails
+//! without one unless `SKIP_GPU_TESTS=1` is set.
+
+mod common;
+
+use common::render_target;
+use engine::{
+    blocks, camera::Camera, chunk_gen::FlatGenerator, irradiance::BounceLight,
+    lighting::AmbientOcclusion, world::VoxelCoord, Scene,
+};
+use std::sync::Arc;
+
+/// Brightness of the pixel in the middle of the image.
+fn centre(image: &image::RgbaImage) -> u32 {
+    let pixel = image.get_pixel(image.width() / 2, image.height() / 2);
+    pixel.0[..3].iter().map(|&v| v as u32).sum()
+}
+
+#[tokio::test]
+async fn walls_in_the_next_chunk_darken_the_corner() {
+    let Some(target) = render_target(32, 32).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut scene = Scene::new(device, &target.config, Arc::new(FlatGenerator::default()));
+    // only the ambient light is left to look at
+    scene.lighting.sun_color = [0.0; 3];
+    scene.world.generate_chunk(device, queue, (0, 0, 0).into());
+    scene.world.generate_chunk(device, queue, (1, 0, 0).into());
+    let light = BounceLight::new(&scene.lighting, &scene.blocks);
+    scene.world.upload_dirty(queue, &light);
+    scene.world.finish_lighting(device, queue);
+
+    // looking down at the last voxel of ground before the border
+    let mut camera = Camera::default(&target.config);
+    camera.eye = (0.995, 0.3, 0.0).into();
+    camera.target = (0.995, 0.0, 0.01).into();
+    let render = |scene: &mut Scene, mode| {
+        scene.lighting.ambient_occlusion = mode;
+        centre(&target.render(scene, &camera))
+    };
+    let modes = [AmbientOcclusion::Vertex, AmbientOcclusion::Rays];
+    let open = modes.map(|mode| render(&mut scene, mode));
+
+    // a wall right past the border, in the other chunk
+    for y in 50..60 {
+        for z in 0..100 {
+            scene
+                .world
+                .set_voxel(VoxelCoord::new(100, y, z), blocks::STONE);
+        }
+    }
+    scene.world.upload_dirty(queue, &light);
+    scene.world.finish_lighting(device, queue);
+    for (mode, open) in modes.into_iter().zip(open) {
+        let occluded = render(&mut scene, mode);
+        assert!(
+            occluded < open * 9 / 10,
+            "{mode:?}: {occluded} isn't darker than {open}"
+        );
+    }
+}
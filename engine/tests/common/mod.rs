@@ -0,0 +1,17 @@
+//! Helpers shared by the tests that need a GPU.
+
+use engine::display_handler::OffscreenWindow;
+
+/// A software render target, or `None` when there's no adapter and `SKIP_GPU_TESTS`
+/// is set. Without the variable a missing adapter fails the test, so nothing goes
+/// green without having run.
+pub async fn render_target(width: u32, height: u32) -> Option<OffscreenWindow> {
+    match OffscreenWindow::new(width, height, true).await {
+        Ok(target) => Some(target),
+        Err(err) if std::env::var_os("SKIP_GPU_TESTS").is_some() => {
+            eprintln!("skipping, no software adapter: {err}");
+            None
+        }
+        Err(err) => panic!("no software adapter ({err}), set SKIP_GPU_TESTS=1 to skip"),
+    }
+}
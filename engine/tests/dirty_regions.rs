@@ -0,0 +1,71 @@
+//! How `VoxelChunk` coalesces the boxes edited between uploads.
+
+use engine::{
+    blocks,
+    voxel::{VoxelChunk, VoxelRegion, MAX_DIRTY_REGIONS},
+};
+
+fn region(min: [u32; 3], max: [u32; 3]) -> VoxelRegion {
+    VoxelRegion { min, max }
+}
+
+#[test]
+fn touching_needs_a_shared_face() {
+    let a = region([0, 0, 0], [2, 2, 2]);
+    assert!(a.touches(&region([1, 1, 1], [3, 3, 3])), "overlapping");
+    assert!(a.touches(&region([2, 0, 0], [4, 2, 2])), "sharing a face");
+    assert!(
+        a.touches(&region([2, 1, 1], [3, 2, 2])),
+        "sharing part of a face"
+    );
+    assert!(!a.touches(&region([2, 2, 0], [4, 4, 2])), "sharing an edge");
+    assert!(
+        !a.touches(&region([2, 2, 2], [3, 3, 3])),
+        "sharing a corner"
+    );
+    assert!(!a.touches(&region([3, 0, 0], [4, 2, 2])), "a voxel apart");
+}
+
+#[test]
+fn neighbouring_edits_coalesce() {
+    let mut chunk = VoxelChunk::new(16);
+    // a row of voxels grows into one box, whichever end it is built from
+    for x in [4, 6, 5, 3] {
+        chunk.set(x, 2, 2, blocks::STONE);
+    }
+    // only diagonal to the row, so it stays on its own
+    chunk.set(7, 3, 2, blocks::STONE);
+
+    let mut dirty = chunk.take_dirty();
+    dirty.sort_by_key(|r| r.min);
+    assert_eq!(
+        dirty,
+        [region([3, 2, 2], [7, 3, 3]), VoxelRegion::voxel(7, 3, 2)]
+    );
+    assert!(!chunk.is_dirty());
+}
+
+#[test]
+fn merging_can_join_earlier_boxes() {
+    let mut chunk = VoxelChunk::new(16);
+    chunk.set(0, 0, 0, blocks::STONE);
+    chunk.set(4, 0, 0, blocks::STONE);
+    // the bridge touches both ends, all three end up in one box
+    chunk.fill_region(region([1, 0, 0], [4, 1, 1]), blocks::DIRT);
+    assert_eq!(chunk.take_dirty(), [region([0, 0, 0], [5, 1, 1])]);
+}
+
+#[test]
+fn too_many_edits_collapse_into_one_box() {
+    let mut chunk = VoxelChunk::new(100);
+    for i in 0..MAX_DIRTY_REGIONS as u32 {
+        chunk.set(i * 2, 0, 0, blocks::STONE);
+    }
+    assert_eq!(chunk.take_dirty().len(), MAX_DIRTY_REGIONS);
+
+    for i in 0..=MAX_DIRTY_REGIONS as u32 {
+        chunk.set(i * 2, 5, 3, blocks::STONE);
+    }
+    let last = MAX_DIRTY_REGIONS as u32 * 2;
+    assert_eq!(chunk.take_dirty(), [region([0, 5, 3], [last + 1, 6, 4])]);
+}
@@ -0,0 +1,91 @@
+//! Loading chunk dumps with `FileGenerator` and rebuilding generators by name.
+
+use engine::{
+    blocks,
+    chunk_gen::{self, FileGenerator, TerrainGenerator, CHUNK_SIZE},
+    world::ChunkCoord,
+};
+use std::{io::ErrorKind, path::PathBuf};
+
+/// An empty directory of its own for every test.
+fn chunk_dir(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR"))
+        .join("file_generator")
+        .join(name);
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn chunk_bytes(id: u8) -> Vec<u8> {
+    vec![id; CHUNK_SIZE.pow(3) as usize]
+}
+
+#[test]
+fn loads_chunk_files() {
+    let dir = chunk_dir("loads");
+    std::fs::write(dir.join("1_-2_3.chunk"), chunk_bytes(blocks::SAND)).unwrap();
+    std::fs::write(dir.join("notes.txt"), "not a chunk").unwrap();
+
+    let generator = FileGenerator::new(&dir).unwrap();
+    let chunk = generator.generate(ChunkCoord::new(1, -2, 3));
+    assert_eq!(chunk.get(5, 5, 5), blocks::SAND);
+    assert!(!chunk.is_modified());
+    // chunks without a file are air
+    let empty = generator.generate(ChunkCoord::new(0, 0, 0));
+    assert_eq!(empty.to_bytes(), chunk_bytes(blocks::AIR));
+}
+
+#[test]
+fn rejects_bad_chunk_files() {
+    let dir = chunk_dir("wrong_size");
+    std::fs::write(dir.join("0_0_0.chunk"), [blocks::STONE; 10]).unwrap();
+    let err = FileGenerator::new(&dir).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(err.to_string().contains("0_0_0.chunk"), "{err}");
+
+    let dir = chunk_dir("misnamed");
+    std::fs::write(dir.join("0_0.chunk"), chunk_bytes(blocks::STONE)).unwrap();
+    let err = FileGenerator::new(&dir).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    let missing = chunk_dir("missing").join("nothing here");
+    assert!(FileGenerator::new(missing).is_err());
+}
+
+#[test]
+fn ids_rebuild_the_same_generator() {
+    let dir = chunk_dir("by_name");
+    std::fs::write(dir.join("0_1_0.chunk"), chunk_bytes(blocks::SNOW)).unwrap();
+    let generator = FileGenerator::new(&dir).unwrap();
+
+    let rebuilt = chunk_gen::from_name(&generator.id(), generator.seed()).unwrap();
+    assert_eq!(rebuilt.id(), generator.id());
+    assert_eq!(
+        rebuilt.generate(ChunkCoord::new(0, 1, 0)).to_bytes(),
+        chunk_bytes(blocks::SNOW)
+    );
+
+    // the same directory by a roundabout path still gets the same id
+    let roundabout = dir.join("..").join("by_name");
+    assert_eq!(FileGenerator::new(roundabout).unwrap().id(), generator.id());
+    let path = generator.id().strip_prefix("file:").unwrap().to_string();
+    assert!(std::path::Path::new(&path).is_absolute(), "{path}");
+
+    for name in ["caves", "heightmap", "flat"] {
+        assert_eq!(chunk_gen::from_name(name, 3).unwrap().id(), name);
+    }
+    let err = chunk_gen::from_name("marble", 3).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
+
+#[cfg(unix)]
+#[test]
+fn refuses_paths_ids_cant_hold() {
+    use std::{ffi::OsStr, os::unix::ffi::OsStrExt};
+
+    let dir = chunk_dir("not_utf8").join(OsStr::from_bytes(b"\xff"));
+    std::fs::create_dir_all(&dir).unwrap();
+    let err = FileGenerator::new(&dir).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidInput);
+}
@@ -0,0 +1,113 @@
+//! Renders the generated chunk from a few fixed camera poses on a software adapter
+//! and compares the result against the reference images in `tests/golden/`.
+//!
+//! Run with `UPDATE_GOLDEN=1` to overwrite the references after an intended change.
+//! Fails without a software adapter unless `SKIP_GPU_TESTS=1` is set.
+
+mod common;
+
+use common::render_target;
+use engine::{camera::Camera, chunk_gen::CaveGenerator, irradiance::BounceLight, Scene};
+use image::{Rgba, RgbaImage};
+use std::{path::PathBuf, sync::Arc};
+
+const SIZE: u32 = 128;
+/// Largest per-channel difference that still counts as the same pixel.
+const PIXEL_TOLERANCE: u8 = 3;
+/// Fraction of pixels allowed to exceed the tolerance before a pose fails.
+const MAX_MISMATCH: f32 = 0.002;
+
+const POSES: &[(&str, [f32; 3], [f32; 3])] = &[
+    ("front", [0.0, 0.5, 3.0], [0.0, 0.0, 0.0]),
+    ("corner", [2.5, 2.0, 2.5], [0.0, 0.0, 0.0]),
+    ("below", [-2.0, -1.5, 2.0], [0.0, 0.0, 0.0]),
+    ("grazing", [3.0, 0.2, 0.4], [0.0, 0.1, 0.0]),
+    ("inside", [-0.2, -0.55, -0.55], [0.5, -0.3, 0.3]),
+];
+
+fn golden_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden")
+}
+
+fn diff_dir() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden_diff")
+}
+
+/// Returns the number of mismatched pixels and an image with them marked red.
+fn compare(actual: &RgbaImage, expected: &RgbaImage) -> (usize, RgbaImage) {
+    let mut diff = RgbaImage::new(actual.width(), actual.height());
+    let mut mismatched = 0;
+
+    for (x, y, a) in actual.enumerate_pixels() {
+        let e = expected.get_pixel(x, y);
+        let off =
+            a.0.iter()
+                .zip(e.0)
+                .any(|(a, e)| a.abs_diff(e) > PIXEL_TOLERANCE);
+
+        let pixel = if off {
+            mismatched += 1;
+            Rgba([255, 0, 0, 255])
+        } else {
+            // faded copy of the reference so the failures are easy to place
+            Rgba([e[0] / 4, e[1] / 4, e[2] / 4, 255])
+        };
+        diff.put_pixel(x, y, pixel);
+    }
+
+    (mismatched, diff)
+}
+
+#[tokio::test]
+async fn raymarcher_matches_golden_images() {
+    let Some(target) = render_target(SIZE, SIZE).await else {
+        return;
+    };
+    let mut scene = Scene::new(
+        &target.device,
+        &target.config,
+        Arc::new(CaveGenerator::new(5)),
+    );
+    scene
+        .world
+        .generate_chunk(&target.device, &target.queue, (0, 0, 0).into());
+    let light = BounceLight::new(&scene.lighting, &scene.blocks);
+    scene.world.upload_dirty(&target.queue, &light);
+    scene.world.finish_lighting(&target.device, &target.queue);
+    let update = std::env::var_os("UPDATE_GOLDEN").is_some();
+
+    let mut failures = vec![];
+    for (name, eye, look_at) in POSES {
+        let mut camera = Camera::default(&target.config);
+        camera.eye = (*eye).into();
+        camera.target = (*look_at).into();
+
+        let actual = target.render(&mut scene, &camera);
+        let golden_path = golden_dir().join(format!("{name}.png"));
+
+        if update {
+            actual.save(&golden_path).unwrap();
+            continue;
+        }
+
+        let expected = image::open(&golden_path)
+            .unwrap_or_else(|e| panic!("missing {}: {e}", golden_path.display()))
+            .into_rgba8();
+        assert_eq!(actual.dimensions(), expected.dimensions(), "{name}");
+
+        let (mismatched, diff) = compare(&actual, &expected);
+        if mismatched as f32 > (SIZE * SIZE) as f32 * MAX_MISMATCH {
+            std::fs::create_dir_all(diff_dir()).unwrap();
+            let actual_path = diff_dir().join(format!("{name}.actual.png"));
+            let diff_path = diff_dir().join(format!("{name}.diff.png"));
+            actual.save(&actual_path).unwrap();
+            diff.save(&diff_path).unwrap();
+            failures.push(format!(
+                "{name}: {mismatched} pixels differ, see {}",
+                diff_path.display()
+            ));
+        }
+    }
+
+    assert!(failures.is_empty(), "{}", failures.join("\n"));
+}
@@ -0,0 +1,36 @@
+//! Picking hotbar slots on `BlockInteraction` with the scroll wheel.
+
+use engine::{blocks, interaction::BlockInteraction};
+use winit::event::{DeviceEvent, MouseScrollDelta};
+
+fn scroll(lines: f32) -> DeviceEvent {
+    DeviceEvent::MouseWheel {
+        delta: MouseScrollDelta::LineDelta(0.0, lines),
+    }
+}
+
+#[test]
+fn scrolling_wraps_around_the_hotbar() {
+    let mut interaction = BlockInteraction::new(vec![blocks::STONE, blocks::DIRT, blocks::LAMP]);
+    assert_eq!(interaction.selected_block(), blocks::STONE);
+
+    interaction.process_events(&scroll(-1.0));
+    assert_eq!(interaction.selected_block(), blocks::DIRT);
+    interaction.process_events(&scroll(2.0));
+    assert_eq!(interaction.selected_block(), blocks::LAMP);
+
+    // half lines add up before they move the selection
+    interaction.process_events(&scroll(-0.5));
+    assert_eq!(interaction.selected(), 2);
+    interaction.process_events(&scroll(-0.5));
+    assert_eq!(interaction.selected(), 0);
+
+    interaction.select(4);
+    assert_eq!(interaction.selected_block(), blocks::DIRT);
+}
+
+#[test]
+#[should_panic(expected = "at least one block")]
+fn an_empty_hotbar_is_refused() {
+    BlockInteraction::new(Vec::new());
+}
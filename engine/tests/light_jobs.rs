@@ -0,0 +1,210 @@
+//! Lighting a `World` through `LightJob`s the way `ChunkStreamer` does, minus the
+//! blocking pool. Needs a software adapter, which fails without one unless
+//! `SKIP_GPU_TESTS=1` is set.
+
+mod common;
+
+use cgmath::{Point3, Vector3};
+use common::render_target;
+use engine::{
+    blocks::{self, BlockRegistry},
+    chunk_gen::FlatGenerator,
+    irradiance::BounceLight,
+    light_levels::MAX_LIGHT,
+    lighting::Lighting,
+    world::{ChunkCoord, VoxelCoord, World},
+};
+use std::sync::Arc;
+
+fn light(sun_direction: Vector3<f32>) -> BounceLight {
+    let lighting = Lighting {
+        sun_direction,
+        ..Default::default()
+    };
+    BounceLight::new(&lighting, &BlockRegistry::default())
+}
+
+/// A probe just above the flat ground.
+fn probe(world: &World) -> [u8; 4] {
+    let chunk = world.get_chunk(ChunkCoord::new(0, 0, 0)).unwrap();
+    chunk.probes.probe(8, 8, 8)
+}
+
+#[tokio::test]
+async fn new_suns_are_left_to_light_jobs() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut world = World::new(device, Arc::new(FlatGenerator::default()));
+    // the probes read back below are the CPU ones
+    world.bake_probes_on_cpu();
+    world.generate_chunk(device, queue, ChunkCoord::new(0, 0, 0));
+
+    // nothing to light with yet
+    assert!(world.next_light_job(Point3::new(0.0, 0.0, 0.0)).is_none());
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+    let levels = &world
+        .get_chunk(ChunkCoord::new(0, 0, 0))
+        .unwrap()
+        .light_levels;
+    assert!(!levels.is_built());
+    world.finish_lighting(device, queue);
+    let levels = &world
+        .get_chunk(ChunkCoord::new(0, 0, 0))
+        .unwrap()
+        .light_levels;
+    assert_eq!(levels.sky(50, 60, 50), MAX_LIGHT);
+    let lit = probe(&world);
+    assert!(lit[..3].iter().any(|&v| v > 0), "got {lit:?}");
+
+    // the sun going under only marks the probes stale
+    world.upload_dirty(queue, &light(-Vector3::unit_y()));
+    assert_eq!(probe(&world), lit);
+    let job = world.next_light_job(Point3::new(0.0, 0.0, 0.0)).unwrap();
+    assert_eq!(job.coord(), ChunkCoord::new(0, 0, 0));
+    // and a chunk with a job out isn't handed out twice
+    assert!(world.next_light_job(Point3::new(0.0, 0.0, 0.0)).is_none());
+
+    world.finish_light_job(queue, job.run());
+    assert_eq!(probe(&world), [0, 0, 0, 255]);
+    assert!(world.next_light_job(Point3::new(0.0, 0.0, 0.0)).is_none());
+}
+
+#[tokio::test]
+async fn edits_throw_away_jobs_started_before() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut world = World::new(device, Arc::new(FlatGenerator::default()));
+    // the probes read back below are the CPU ones
+    world.bake_probes_on_cpu();
+    world.generate_chunk(device, queue, ChunkCoord::new(0, 0, 0));
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+
+    let job = world.next_light_job(Point3::new(0.0, 0.0, 0.0)).unwrap();
+    assert!(world.set_voxel(VoxelCoord::new(50, 60, 50), blocks::STONE));
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+    world.finish_light_job(queue, job.run());
+
+    // baked from the voxels before the edit, so it's still stale
+    assert!(!world
+        .get_chunk(ChunkCoord::new(0, 0, 0))
+        .unwrap()
+        .probes
+        .is_baked());
+    assert!(world.next_light_job(Point3::new(0.0, 0.0, 0.0)).is_some());
+}
+
+#[tokio::test]
+async fn caves_under_loaded_ground_stay_dark() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut world = World::new(device, Arc::new(FlatGenerator::default()));
+    // only the light levels matter here
+    world.set_probe_resolution(device, queue, 2);
+    let (above, below) = (ChunkCoord::new(0, 0, 0), ChunkCoord::new(0, -1, 0));
+    world.generate_chunk(device, queue, above);
+    world.generate_chunk(device, queue, below);
+
+    // a cave right under the top of the lower chunk, which is all stone
+    for x in 40..45 {
+        for y in -5..0 {
+            for z in 40..45 {
+                world.set_voxel(VoxelCoord::new(x, y, z), blocks::AIR);
+            }
+        }
+    }
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+    world.finish_lighting(device, queue);
+    let cave = |world: &World| world.get_chunk(below).unwrap().light_levels.sky(42, 98, 42);
+    assert_eq!(cave(&world), 0);
+
+    // a shaft down through the ground above
+    for y in 0..50 {
+        world.set_voxel(VoxelCoord::new(42, y, 42), blocks::AIR);
+    }
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+    world.finish_lighting(device, queue);
+    assert_eq!(cave(&world), MAX_LIGHT);
+}
+
+#[tokio::test]
+async fn edits_are_relit_by_the_next_job() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut world = World::new(device, Arc::new(FlatGenerator::default()));
+    // the probes read back below are the CPU ones
+    world.bake_probes_on_cpu();
+    world.generate_chunk(device, queue, ChunkCoord::new(0, 0, 0));
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+    world.finish_lighting(device, queue);
+    let lit = probe(&world);
+    let sky = |world: &World| {
+        let chunk = world.get_chunk(ChunkCoord::new(0, 0, 0)).unwrap();
+        chunk.light_levels.sky(55, 55, 55)
+    };
+    assert_eq!(sky(&world), MAX_LIGHT);
+
+    // a roof over the probe
+    for x in 30..80 {
+        for z in 30..80 {
+            world.set_voxel(VoxelCoord::new(x, 60, z), blocks::STONE);
+        }
+    }
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+    assert_eq!(probe(&world), lit);
+    assert_eq!(sky(&world), MAX_LIGHT);
+
+    // a job thrown away by a later edit leaves the roof for the next one
+    let job = world.next_light_job(Point3::new(0.0, 0.0, 0.0)).unwrap();
+    world.set_voxel(VoxelCoord::new(10, 60, 10), blocks::STONE);
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+    world.finish_light_job(queue, job.run());
+    assert_eq!(probe(&world), lit);
+    assert_eq!(sky(&world), MAX_LIGHT);
+
+    world.finish_lighting(device, queue);
+    assert!(sky(&world) < MAX_LIGHT);
+    let brightness = |probe: [u8; 4]| probe[..3].iter().map(|&v| v as u32).sum::<u32>();
+    let shaded = probe(&world);
+    assert!(
+        brightness(shaded) < brightness(lit),
+        "{shaded:?} isn't darker than {lit:?}"
+    );
+}
+
+#[tokio::test]
+async fn probes_look_into_neighbours_that_stream_in_later() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut world = World::new(device, Arc::new(FlatGenerator::default()));
+    // the probes read back below are the CPU ones
+    world.bake_probes_on_cpu();
+    world.generate_chunk(device, queue, ChunkCoord::new(0, 0, 0));
+    world.upload_dirty(queue, &light(Vector3::unit_y()));
+    world.finish_lighting(device, queue);
+    // the last probe along x, whose rays run out of the chunk and found nothing
+    let edge = |world: &World| {
+        let chunk = world.get_chunk(ChunkCoord::new(0, 0, 0)).unwrap();
+        chunk.probes.probe(15, 8, 8)
+    };
+    let alone = edge(&world);
+
+    // more lit ground next door
+    world.generate_chunk(device, queue, ChunkCoord::new(1, 0, 0));
+    world.finish_lighting(device, queue);
+    let brightness = |probe: [u8; 4]| probe[..3].iter().map(|&v| v as u32).sum::<u32>();
+    assert!(
+        brightness(edge(&world)) > brightness(alone),
+        "{:?} isn't brighter than {alone:?}",
+        edge(&world)
+    );
+}
@@ -0,0 +1,278 @@
+//! Flood fills `LightLevels` on small chunks and checks the levels voxel by voxel.
+
+use engine::{
+    blocks::{self, BlockRegistry},
+    light_levels::{BlockLight, LightLevels, MAX_LIGHT},
+    voxel::{VoxelChunk, VoxelRegion},
+};
+
+fn block_light() -> BlockLight {
+    BlockLight::new(&BlockRegistry::default())
+}
+
+fn light(chunk: &VoxelChunk) -> LightLevels {
+    let mut levels = LightLevels::new(chunk.size());
+    levels.update(chunk, chunk.bounds(), &block_light());
+    levels
+}
+
+/// Lights `chunk` with `neighbour`'s light shining in along `side`.
+fn light_next_to(chunk: &VoxelChunk, side: usize, neighbour: &LightLevels) -> LightLevels {
+    let mut levels = LightLevels::new(chunk.size());
+    levels.set_border(side, &neighbour.face(side ^ 1));
+    levels.update(chunk, chunk.bounds(), &block_light());
+    levels
+}
+
+fn assert_same(a: &LightLevels, b: &LightLevels, size: u32) {
+    for z in 0..size {
+        for y in 0..size {
+            for x in 0..size {
+                assert_eq!(
+                    (a.sky(x, y, z), a.block(x, y, z)),
+                    (b.sky(x, y, z), b.block(x, y, z)),
+                    "voxel {x} {y} {z}"
+                );
+            }
+        }
+    }
+}
+
+#[test]
+fn open_sky_reaches_the_floor() {
+    let mut chunk = VoxelChunk::new(8);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0; 3],
+            max: [8, 2, 8],
+        },
+        blocks::STONE,
+    );
+    let levels = light(&chunk);
+    assert!(levels.is_built());
+    assert_eq!(levels.sky(3, 2, 3), MAX_LIGHT);
+    assert_eq!(levels.sky(3, 7, 3), MAX_LIGHT);
+    // solid blocks stay dark
+    assert_eq!(levels.sky(3, 1, 3), 0);
+}
+
+#[test]
+fn sky_light_fades_under_a_roof() {
+    let mut chunk = VoxelChunk::new(16);
+    // a roof over x 4.., open to the sky for x < 4
+    chunk.fill_region(
+        VoxelRegion {
+            min: [4, 10, 0],
+            max: [16, 11, 16],
+        },
+        blocks::STONE,
+    );
+    let levels = light(&chunk);
+    assert_eq!(levels.sky(3, 5, 8), MAX_LIGHT);
+    for x in 4..16 {
+        assert_eq!(levels.sky(x, 5, 8), MAX_LIGHT - (x - 3) as u8, "x {x}");
+    }
+}
+
+#[test]
+fn glass_roofs_let_the_sky_in() {
+    let mut chunk = VoxelChunk::new(16);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0, 10, 0],
+            max: [16, 11, 16],
+        },
+        blocks::GLASS,
+    );
+    // with water under part of it
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0, 0, 0],
+            max: [8, 4, 16],
+        },
+        blocks::WATER,
+    );
+    let levels = light(&chunk);
+    for x in 0..16 {
+        assert_eq!(levels.sky(x, 10, 8), MAX_LIGHT, "glass at x {x}");
+        assert_eq!(levels.sky(x, 5, 8), MAX_LIGHT, "x {x}");
+        assert_eq!(levels.sky(x, 0, 8), MAX_LIGHT, "floor at x {x}");
+    }
+
+    // the same roof in stone shuts it out
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0, 10, 0],
+            max: [16, 11, 16],
+        },
+        blocks::STONE,
+    );
+    assert_eq!(light(&chunk).sky(8, 5, 8), 0);
+}
+
+#[test]
+fn lamps_light_their_surroundings() {
+    let mut chunk = VoxelChunk::new(16);
+    chunk.fill(blocks::STONE);
+    // a sealed room, so no sky light gets in
+    chunk.fill_region(
+        VoxelRegion {
+            min: [1; 3],
+            max: [15; 3],
+        },
+        blocks::AIR,
+    );
+    chunk.set(8, 8, 8, blocks::LAMP);
+
+    let levels = light(&chunk);
+    let lamp = levels.block(8, 8, 8);
+    assert!(lamp > 10, "lamp only gives off {lamp}");
+    assert_eq!(levels.block(9, 8, 8), lamp - 1);
+    // manhattan distance 4
+    assert_eq!(levels.block(10, 9, 7), lamp - 4);
+    assert_eq!(levels.sky(10, 9, 7), 0);
+    assert_eq!(levels.block(0, 8, 8), 0);
+}
+
+/// Relighting around edits has to end up where lighting from scratch does.
+#[test]
+fn updates_match_a_full_relight() {
+    let size = 48;
+    let mut chunk = VoxelChunk::new(size);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0; 3],
+            max: [size, 20, size],
+        },
+        blocks::STONE,
+    );
+    // a cave with a shaft up to the surface
+    chunk.fill_region(
+        VoxelRegion {
+            min: [5, 5, 5],
+            max: [40, 12, 40],
+        },
+        blocks::AIR,
+    );
+    chunk.fill_region(
+        VoxelRegion {
+            min: [20, 12, 20],
+            max: [23, 20, 23],
+        },
+        blocks::AIR,
+    );
+    let mut levels = light(&chunk);
+    let blocks = block_light();
+
+    let edits = [
+        // cap the shaft
+        (VoxelRegion::voxel(21, 19, 21), blocks::STONE),
+        (
+            VoxelRegion {
+                min: [20, 19, 20],
+                max: [23, 20, 23],
+            },
+            blocks::DIRT,
+        ),
+        // light the cave and dig a second way in
+        (VoxelRegion::voxel(30, 6, 30), blocks::LAMP),
+        (
+            VoxelRegion {
+                min: [35, 12, 35],
+                max: [37, 20, 37],
+            },
+            blocks::AIR,
+        ),
+        // and put the lamp out again
+        (VoxelRegion::voxel(30, 6, 30), blocks::AIR),
+    ];
+    for (region, id) in edits {
+        chunk.fill_region(region, id);
+        let touched = levels.update(&chunk, region, &blocks);
+        assert_ne!(
+            touched,
+            chunk.bounds(),
+            "edits shouldn't relight everything"
+        );
+        assert_same(&levels, &light(&chunk), size);
+    }
+}
+
+/// Sides of a chunk, indices into `NEIGHBOURS`.
+const UP: usize = 1;
+const WEST: usize = 2;
+
+#[test]
+fn ground_in_the_chunk_above_shades_caves_below() {
+    let size = 16;
+    // solid ground at the bottom of the upper chunk
+    let mut upper = VoxelChunk::new(size);
+    upper.fill_region(
+        VoxelRegion {
+            min: [0; 3],
+            max: [size, 4, size],
+        },
+        blocks::STONE,
+    );
+    // a cave open to the top of the lower one
+    let mut lower = VoxelChunk::new(size);
+    lower.fill(blocks::STONE);
+    lower.fill_region(
+        VoxelRegion {
+            min: [4, 8, 4],
+            max: [12, size, 12],
+        },
+        blocks::AIR,
+    );
+
+    // on its own the cave would be under the open sky
+    assert_eq!(light(&lower).sky(8, 10, 8), MAX_LIGHT);
+    let shaded = light_next_to(&lower, UP, &light(&upper));
+    for y in 8..size {
+        assert_eq!(shaded.sky(8, y, 8), 0, "y {y}");
+    }
+
+    // a shaft through the ground lets the sky back in, straight down at full light
+    upper.fill_region(
+        VoxelRegion {
+            min: [8, 0, 8],
+            max: [9, 4, 9],
+        },
+        blocks::AIR,
+    );
+    let lit = light_next_to(&lower, UP, &light(&upper));
+    assert_eq!(lit.sky(8, size - 1, 8), MAX_LIGHT);
+    assert_eq!(lit.sky(8, 8, 8), MAX_LIGHT);
+    assert_eq!(lit.sky(10, 8, 8), MAX_LIGHT - 2);
+}
+
+#[test]
+fn block_light_crosses_into_the_next_chunk() {
+    let size = 16;
+    let mut west = VoxelChunk::new(size);
+    west.fill(blocks::STONE);
+    west.fill_region(
+        VoxelRegion {
+            min: [8, 4, 4],
+            max: [size, 12, 12],
+        },
+        blocks::AIR,
+    );
+    west.set(13, 8, 8, blocks::LAMP);
+    let mut east = VoxelChunk::new(size);
+    east.fill(blocks::STONE);
+    east.fill_region(
+        VoxelRegion {
+            min: [0, 4, 4],
+            max: [8, 12, 12],
+        },
+        blocks::AIR,
+    );
+
+    let west_levels = light(&west);
+    let east_levels = light_next_to(&east, WEST, &west_levels);
+    let edge = west_levels.block(size - 1, 8, 8);
+    assert!(edge > 0);
+    assert_eq!(east_levels.block(0, 8, 8), edge - 1);
+    assert_eq!(east_levels.block(3, 8, 8), edge - 4);
+}
@@ -0,0 +1,123 @@
+//! `OccupancyMips` kept up to date around edits, checked against building it from
+//! scratch. 100³ chunks have odd levels of 25 and 3 cells whose last row has no
+//! parent, so the edits sit on and around those edges.
+
+use engine::{
+    blocks,
+    occupancy::{level_offset, mip_level_count, OccupancyMips},
+    voxel::{VoxelChunk, VoxelRegion},
+};
+
+const SIZE: u32 = 100;
+
+fn assert_same(a: &OccupancyMips, b: &OccupancyMips) {
+    assert_eq!(a.len(), b.len());
+    for mip in 1..=a.len() {
+        let size = SIZE >> mip;
+        for z in 0..size {
+            for y in 0..size {
+                for x in 0..size {
+                    assert_eq!(
+                        a.is_solid(mip, x, y, z),
+                        b.is_solid(mip, x, y, z),
+                        "mip {mip} cell {x} {y} {z}"
+                    );
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn levels_stack_without_overlapping() {
+    let levels = mip_level_count(SIZE);
+    assert_eq!(levels, 7);
+    for mip in 1..levels - 1 {
+        let end = level_offset(SIZE, mip) + (SIZE >> mip);
+        assert!(
+            end <= level_offset(SIZE, mip + 1),
+            "mip {mip} runs into the next"
+        );
+    }
+}
+
+#[test]
+fn updates_match_a_full_build() {
+    let mut chunk = VoxelChunk::new(SIZE);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0; 3],
+            max: [SIZE, 40, SIZE],
+        },
+        blocks::STONE,
+    );
+    let mut mips = OccupancyMips::build(&chunk);
+
+    let edits = [
+        // the far corner, outside every odd level's last full cell
+        (VoxelRegion::voxel(99, 99, 99), blocks::SAND),
+        // across the edge of the 12 cells of mip 3, 96 voxels in
+        (
+            VoxelRegion {
+                min: [94, 60, 94],
+                max: [98, 62, 98],
+            },
+            blocks::DIRT,
+        ),
+        // across the middle along x and the cell edge at 64 voxels along z
+        (
+            VoxelRegion {
+                min: [47, 70, 63],
+                max: [53, 72, 66],
+            },
+            blocks::GRASS,
+        ),
+        // clear them again, the levels above have to go back to empty
+        (VoxelRegion::voxel(99, 99, 99), blocks::AIR),
+        (
+            VoxelRegion {
+                min: [94, 60, 94],
+                max: [98, 62, 98],
+            },
+            blocks::AIR,
+        ),
+        // dig into the ground along the top edge
+        (
+            VoxelRegion {
+                min: [0, 30, 96],
+                max: [SIZE, 40, SIZE],
+            },
+            blocks::AIR,
+        ),
+    ];
+    for (region, id) in edits {
+        let before = mips.clone();
+        chunk.fill_region(region, id);
+        let touched = mips.update(&chunk, region);
+        assert_eq!(touched.len(), mips.len());
+        let fresh = OccupancyMips::build(&chunk);
+        assert_same(&mips, &fresh);
+
+        // anything that changed has to be in the boxes handed back for upload
+        for (mip, cells) in (1..).zip(&touched) {
+            let size = SIZE >> mip;
+            for z in 0..size {
+                for y in 0..size {
+                    for x in 0..size {
+                        let inside = (0..3).all(|i| {
+                            let v = [x, y, z][i];
+                            (cells.min[i]..cells.max[i]).contains(&v)
+                        });
+                        if !inside {
+                            assert_eq!(
+                                before.is_solid(mip, x, y, z),
+                                fresh.is_solid(mip, x, y, z),
+                                "mip {mip} cell {x} {y} {z} changed outside {cells:?}"
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,169 @@
+//! Block picking: `raycast::raycast` through the loaded chunks of a `World` on a
+//! software adapter, which fails without one unless `SKIP_GPU_TESTS=1` is set, and
+//! the camera rays it is fed with.
+
+mod common;
+
+use cgmath::{InnerSpace, Point3, Vector3, Vector4};
+use common::render_target;
+use engine::{
+    blocks,
+    camera::Camera,
+    chunk_gen::FlatGenerator,
+    raycast::raycast,
+    world::{ChunkCoord, VoxelCoord, World, VOXEL_SIZE},
+};
+use std::sync::Arc;
+use winit::dpi::{PhysicalPosition, PhysicalSize};
+
+/// Flat ground below voxel y 50, with the chunks at `coords` loaded.
+async fn flat_world(coords: &[ChunkCoord]) -> Option<World> {
+    let target = render_target(1, 1).await?;
+    let mut world = World::new(&target.device, Arc::new(FlatGenerator::default()));
+    for coord in coords {
+        world.generate_chunk(&target.device, &target.queue, *coord);
+    }
+    Some(world)
+}
+
+/// World position of a point given in voxel units.
+fn at_voxel(x: f32, y: f32, z: f32) -> Point3<f32> {
+    Point3::new(x, y, z) * VOXEL_SIZE - Vector3::new(1.0, 1.0, 1.0)
+}
+
+fn assert_close(a: f32, b: f32) {
+    assert!((a - b).abs() < 1e-4, "{a} isn't {b}");
+}
+
+#[tokio::test]
+async fn hits_the_ground() {
+    let Some(world) = flat_world(&[ChunkCoord::new(0, 0, 0)]).await else {
+        return;
+    };
+    let hit = raycast(&world, at_voxel(10.5, 80.5, 10.5), -Vector3::unit_y(), 5.0)
+        .expect("looking straight down at the ground");
+    assert_eq!(hit.voxel, VoxelCoord::new(10, 49, 10));
+    assert_eq!(hit.block, blocks::GRASS);
+    assert_eq!(hit.normal, Vector3::new(0, 1, 0));
+    assert_eq!(hit.adjacent(), VoxelCoord::new(10, 50, 10));
+    assert_close(hit.distance, 30.5 * VOXEL_SIZE);
+
+    // at an angle the face is whichever one the ray crossed last
+    let hit = raycast(
+        &world,
+        at_voxel(10.5, 52.5, 10.5),
+        Vector3::new(1.0, -0.2, 0.0),
+        5.0,
+    )
+    .unwrap();
+    assert_eq!(hit.normal, Vector3::new(0, 1, 0));
+    assert_eq!(hit.voxel.y, 49);
+}
+
+#[tokio::test]
+async fn starting_inside_a_block_has_no_normal() {
+    let Some(world) = flat_world(&[ChunkCoord::new(0, 0, 0)]).await else {
+        return;
+    };
+    let hit = raycast(&world, at_voxel(10.5, 20.5, 10.5), Vector3::unit_x(), 5.0).unwrap();
+    assert_eq!(hit.voxel, VoxelCoord::new(10, 20, 10));
+    assert_eq!(hit.normal, Vector3::new(0, 0, 0));
+    assert_eq!(hit.distance, 0.0);
+}
+
+#[tokio::test]
+async fn misses_beyond_max_distance() {
+    let Some(world) = flat_world(&[ChunkCoord::new(0, 0, 0)]).await else {
+        return;
+    };
+    let origin = at_voxel(10.5, 80.5, 10.5);
+    // the ground is 30.5 voxels down
+    assert!(raycast(&world, origin, -Vector3::unit_y(), 29.0 * VOXEL_SIZE).is_none());
+    assert!(raycast(&world, origin, -Vector3::unit_y(), 31.0 * VOXEL_SIZE).is_some());
+    // nothing but air and unloaded chunks above
+    assert!(raycast(&world, origin, Vector3::unit_y(), 5.0).is_none());
+}
+
+#[tokio::test]
+async fn crosses_into_the_next_chunk() {
+    let Some(mut world) = flat_world(&[ChunkCoord::new(0, 1, 0), ChunkCoord::new(1, 1, 0)]).await
+    else {
+        return;
+    };
+    let block = VoxelCoord::new(105, 150, 50);
+    world.set_voxel(block, blocks::STONE);
+
+    let hit = raycast(&world, at_voxel(90.5, 150.5, 50.5), Vector3::unit_x(), 5.0).unwrap();
+    assert_eq!(hit.voxel, block);
+    assert_eq!(hit.block, blocks::STONE);
+    assert_eq!(hit.normal, Vector3::new(-1, 0, 0));
+    assert_close(hit.distance, 14.5 * VOXEL_SIZE);
+
+    // the other way the ray runs into unloaded chunks, which are air
+    assert!(raycast(&world, at_voxel(90.5, 150.5, 50.5), -Vector3::unit_x(), 5.0).is_none());
+}
+
+fn camera() -> Camera {
+    let config = wgpu::SurfaceConfiguration {
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        width: 200,
+        height: 100,
+        present_mode: wgpu::PresentMode::Fifo,
+        alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+        view_formats: vec![],
+    };
+    let mut camera = Camera::default(&config);
+    camera.eye = Point3::new(1.0, 2.0, 3.0);
+    camera.target = Point3::new(-1.0, 0.5, 0.0);
+    camera
+}
+
+#[test]
+fn centre_ray_looks_at_the_target() {
+    let camera = camera();
+    let (origin, dir) = camera.centre_ray();
+    assert_eq!(origin, camera.eye);
+    let expected = (camera.target - camera.eye).normalize();
+    assert!((dir - expected).magnitude() < 1e-4, "{dir:?}");
+
+    let (_, cursor) = camera.cursor_ray(
+        PhysicalPosition::new(100.0, 50.0),
+        PhysicalSize::new(200, 100),
+    );
+    assert!((cursor - expected).magnitude() < 1e-4, "{cursor:?}");
+}
+
+#[test]
+fn screen_rays_project_back_to_where_they_started() {
+    let camera = camera();
+    let view_proj = camera.build_view_projection_matrix();
+    for (x, y) in [(0.0, 0.0), (0.5, -0.25), (-0.9, 0.9)] {
+        let (origin, dir) = camera.screen_ray(x, y);
+        assert!(dir.dot(camera.target - camera.eye) > 0.0);
+        let p = view_proj * (origin + dir * 2.0).to_homogeneous();
+        let Vector4 { x: px, y: py, .. } = p / p.w;
+        assert!(
+            (px - x).abs() < 1e-4 && (py - y).abs() < 1e-4,
+            "{x} {y}: {px} {py}"
+        );
+    }
+}
+
+#[tokio::test]
+async fn ties_step_along_x_first_like_the_shader() {
+    let Some(world) = flat_world(&[ChunkCoord::new(0, 0, 0)]).await else {
+        return;
+    };
+    // from the middle of voxel (12, 62, 12) through voxel corners exactly, where x
+    // and y are crossed at the same time
+    let hit = raycast(
+        &world,
+        Point3::new(-0.75, 0.25, -0.75),
+        Vector3::new(1.0, -1.0, 0.0),
+        5.0,
+    )
+    .unwrap();
+    assert_eq!(hit.voxel, VoxelCoord::new(25, 49, 12));
+    assert_eq!(hit.normal, Vector3::new(0, 1, 0));
+}
@@ -0,0 +1,162 @@
+//! Bookkeeping of `PointLights` on the CPU, ids and the shader-side limit, and more
+//! lights than that reaching the shader where storage buffers are available. The
+//! last needs a software adapter, which fails without one unless `SKIP_GPU_TESTS=1`
+//! is set.
+
+mod common;
+
+use cgmath::Point3;
+use common::render_target;
+use engine::{
+    camera::Camera,
+    chunk_gen::FlatGenerator,
+    irradiance::BounceLight,
+    lighting::{PointLights, MAX_POINT_LIGHTS},
+    Scene,
+};
+use std::sync::Arc;
+
+#[test]
+fn ids_stay_valid_across_removals() {
+    let mut lights = PointLights::default();
+    let red = lights
+        .add_point_light(Point3::new(0.0, 0.0, 0.0), [1.0, 0.0, 0.0], 1.0)
+        .unwrap();
+    let green = lights
+        .add_point_light(Point3::new(1.0, 0.0, 0.0), [0.0, 1.0, 0.0], 2.0)
+        .unwrap();
+    let blue = lights
+        .add_point_light(Point3::new(2.0, 0.0, 0.0), [0.0, 0.0, 1.0], 3.0)
+        .unwrap();
+    assert_eq!(lights.len(), 3);
+
+    assert_eq!(lights.remove(green).map(|light| light.radius), Some(2.0));
+    assert!(lights.remove(green).is_none());
+    assert!(lights.get(green).is_none());
+    assert_eq!(lights.get(red).unwrap().color, [1.0, 0.0, 0.0]);
+    assert_eq!(lights.get(blue).unwrap().color, [0.0, 0.0, 1.0]);
+
+    // ids aren't reused, so a stale one can't pick up a newer light
+    let yellow = lights
+        .add_point_light(Point3::new(3.0, 0.0, 0.0), [1.0, 1.0, 0.0], 1.0)
+        .unwrap();
+    assert_ne!(yellow, green);
+    assert!(lights.get(green).is_none());
+}
+
+#[test]
+fn lights_can_be_moved() {
+    let mut lights = PointLights::default();
+    let id = lights
+        .add_point_light(Point3::new(0.0, 0.0, 0.0), [1.0; 3], 1.0)
+        .unwrap();
+    assert!(lights.get(id).unwrap().shadows);
+
+    let light = lights.get_mut(id).unwrap();
+    light.position = Point3::new(0.0, 5.0, 0.0);
+    light.shadows = false;
+    assert_eq!(lights.get(id).unwrap().position, Point3::new(0.0, 5.0, 0.0));
+    assert!(!lights.get(id).unwrap().shadows);
+}
+
+#[test]
+fn refuses_more_than_the_shader_takes() {
+    let mut lights = PointLights::default();
+    let ids = (0..MAX_POINT_LIGHTS)
+        .map(|_| lights.add_point_light(Point3::new(0.0, 0.0, 0.0), [1.0; 3], 1.0))
+        .collect::<Option<Vec<_>>>()
+        .expect("the shader takes this many");
+    assert!(lights
+        .add_point_light(Point3::new(0.0, 0.0, 0.0), [1.0; 3], 1.0)
+        .is_none());
+    assert_eq!(lights.len(), MAX_POINT_LIGHTS);
+
+    // removing one makes room again
+    lights.remove(ids[0]);
+    assert!(lights
+        .add_point_light(Point3::new(0.0, 0.0, 0.0), [1.0; 3], 1.0)
+        .is_some());
+}
+
+#[test]
+fn lights_without_a_limit_keep_coming() {
+    let mut lights = PointLights::new(None);
+    for _ in 0..MAX_POINT_LIGHTS * 2 {
+        assert!(lights
+            .add_point_light(Point3::new(0.0, 0.0, 0.0), [1.0; 3], 1.0)
+            .is_some());
+    }
+    assert_eq!(lights.len(), MAX_POINT_LIGHTS * 2);
+    // only as many as the buffer has room for go to the shader
+    assert_eq!(
+        lights.to_raw(MAX_POINT_LIGHTS).len() as u64,
+        PointLights::buffer_size(MAX_POINT_LIGHTS)
+    );
+    assert_eq!(
+        lights.to_raw(MAX_POINT_LIGHTS)[..4],
+        (MAX_POINT_LIGHTS as u32).to_ne_bytes()
+    );
+}
+
+#[tokio::test]
+async fn storage_buffers_take_more_than_a_uniform_would() {
+    let Some(target) = render_target(32, 32).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut scene = Scene::new(device, &target.config, Arc::new(FlatGenerator::default()));
+    if device.limits().max_storage_buffers_per_shader_stage == 0 {
+        assert_eq!(scene.point_lights.limit(), Some(MAX_POINT_LIGHTS));
+        return;
+    }
+    assert_eq!(scene.point_lights.limit(), None);
+    scene.world.generate_chunk(device, queue, (0, 0, 0).into());
+    let light = BounceLight::new(&scene.lighting, &scene.blocks);
+    scene.world.upload_dirty(queue, &light);
+    scene.world.finish_lighting(device, queue);
+
+    let mut camera = Camera::default(&target.config);
+    camera.eye = (0.0, 1.5, 0.0).into();
+    camera.target = (0.0, 0.0, 0.01).into();
+    let brightness = |image: image::RgbaImage| {
+        let pixel = image.get_pixel(16, 16);
+        pixel.0[..3].iter().map(|&v| v as u32).sum::<u32>()
+    };
+
+    // a full uniform's worth far off, which light nothing
+    for _ in 0..MAX_POINT_LIGHTS {
+        scene
+            .point_lights
+            .add_point_light(Point3::new(50.0, 50.0, 50.0), [1.0; 3], 0.1);
+    }
+    let unlit = brightness(target.render(&mut scene, &camera));
+    // and one more right over the ground
+    scene
+        .point_lights
+        .add_point_light(Point3::new(0.0, 0.1, 0.0), [4.0, 0.0, 0.0], 0.5);
+    let lit = brightness(target.render(&mut scene, &camera));
+    assert!(lit > unlit, "{lit} isn't brighter than {unlit}");
+}
+
+#[tokio::test]
+async fn webgl2_devices_fall_back_to_a_uniform() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, _queue) = target
+        .adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                label: Some("webgl2_device"),
+                features: wgpu::Features::empty(),
+                limits: wgpu::Limits::downlevel_webgl2_defaults()
+                    .using_resolution(target.adapter.limits()),
+            },
+            None,
+        )
+        .await
+        .unwrap();
+    // builds the pipelines with the uniform variant of the shader
+    let scene = Scene::new(&device, &target.config, Arc::new(FlatGenerator::default()));
+    assert_eq!(scene.point_lights.limit(), Some(MAX_POINT_LIGHTS));
+}
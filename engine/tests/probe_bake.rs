@@ -0,0 +1,129 @@
+//! Bakes irradiance probes in a compute pass and checks they match the CPU bake.
+//! Needs a software adapter, which fails without one unless `SKIP_GPU_TESTS=1` is
+//! set.
+
+mod common;
+
+use cgmath::Vector3;
+use common::render_target;
+use engine::{
+    blocks::{self, BlockRegistry},
+    chunk_gen::FlatGenerator,
+    display_handler::OffscreenWindow,
+    irradiance::{BounceLight, ProbeGrid},
+    lighting::Lighting,
+    texture::Texture,
+    world::{ChunkCoord, VoxelCoord, World},
+};
+use std::sync::Arc;
+
+/// Copies a probe texture back, x-major like `ProbeGrid::probe`.
+fn read_probes(target: &OffscreenWindow, texture: &Texture) -> Vec<[u8; 4]> {
+    let size = texture.texture.size();
+    let row = size.width * 4;
+    let padded_row =
+        row.div_ceil(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT) * wgpu::COPY_BYTES_PER_ROW_ALIGNMENT;
+    let output = target.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: (padded_row * size.height * size.depth_or_array_layers) as u64,
+        usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+        mapped_at_creation: false,
+    });
+    let mut encoder = target
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.copy_texture_to_buffer(
+        texture.texture.as_image_copy(),
+        wgpu::ImageCopyBuffer {
+            buffer: &output,
+            layout: wgpu::ImageDataLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row),
+                rows_per_image: Some(size.height),
+            },
+        },
+        size,
+    );
+    target.queue.submit(Some(encoder.finish()));
+
+    let slice = output.slice(..);
+    slice.map_async(wgpu::MapMode::Read, |res| res.unwrap());
+    target.device.poll(wgpu::Maintain::Wait);
+    let probes = slice
+        .get_mapped_range()
+        .chunks(padded_row as usize)
+        .flat_map(|texels| texels[..row as usize].to_vec())
+        .collect::<Vec<_>>();
+    probes
+        .chunks(4)
+        .map(|texel| texel.try_into().unwrap())
+        .collect()
+}
+
+#[tokio::test]
+async fn compute_bake_matches_the_cpu() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut world = World::new(device, Arc::new(FlatGenerator::default()));
+    assert!(world.probes_on_gpu());
+    let origin = ChunkCoord::new(0, 0, 0);
+    world.generate_chunk(device, queue, origin);
+    world.generate_chunk(device, queue, ChunkCoord::new(1, 0, 0));
+    // a stone wall across the border with a glass window and a pool of water,
+    // for shadows, tinted light and rays going on into the next chunk
+    for y in 50..70 {
+        for z in 20..80 {
+            let id = if (55..60).contains(&y) {
+                blocks::GLASS
+            } else {
+                blocks::STONE
+            };
+            world.set_voxel(VoxelCoord::new(99, y, z), id);
+            world.set_voxel(VoxelCoord::new(100, y, z), id);
+        }
+    }
+    for x in 30..60 {
+        for z in 30..60 {
+            world.set_voxel(VoxelCoord::new(x, 49, z), blocks::WATER);
+        }
+    }
+    let lighting = Lighting {
+        sun_direction: Vector3::new(1.0, 0.7, 0.3),
+        ..Default::default()
+    };
+    let light = BounceLight::new(&lighting, &BlockRegistry::default());
+    world.upload_dirty(queue, &light);
+    world.finish_lighting(device, queue);
+
+    let chunk = world.get_chunk(origin).unwrap();
+    let baked = read_probes(&target, &chunk.probe_texture);
+    let voxels = chunk.voxels.clone();
+    let mut probes = ProbeGrid::new(world.probe_resolution());
+    probes.update_regions(
+        &voxels,
+        &world.neighbour_voxels(origin),
+        &[voxels.bounds()],
+        &light,
+    );
+
+    let resolution = probes.resolution();
+    let mut lit = 0;
+    for z in 0..resolution {
+        for y in 0..resolution {
+            for x in 0..resolution {
+                let cpu = probes.probe(x, y, z);
+                let gpu = baked[(x + y * resolution + z * resolution * resolution) as usize];
+                // the two round a little differently
+                assert!(
+                    cpu.iter().zip(gpu).all(|(&a, b)| a.abs_diff(b) <= 2),
+                    "probe {:?}: {gpu:?} on the GPU, {cpu:?} on the CPU",
+                    [x, y, z]
+                );
+                lit += cpu[..3].iter().any(|&v| v > 0) as u32;
+            }
+        }
+    }
+    assert!(lit > 0);
+}
@@ -0,0 +1,198 @@
+//! Bakes `ProbeGrid`s on the CPU and checks what the probes see.
+
+use cgmath::Vector3;
+use engine::{
+    blocks::{self, BlockRegistry},
+    irradiance::{BounceLight, Neighbours, ProbeGrid},
+    lighting::Lighting,
+    voxel::{VoxelChunk, VoxelRegion},
+};
+use std::sync::Arc;
+
+/// Side of a chunk in `Neighbours`.
+const UP: usize = 1;
+
+fn light(sun_direction: Vector3<f32>) -> BounceLight {
+    let lighting = Lighting {
+        sun_direction,
+        ..Default::default()
+    };
+    BounceLight::new(&lighting, &BlockRegistry::default())
+}
+
+fn bake(chunk: &VoxelChunk, resolution: u32, light: &BounceLight) -> ProbeGrid {
+    let mut probes = ProbeGrid::new(resolution);
+    probes.update(chunk, chunk.bounds(), light);
+    probes
+}
+
+/// A 32³ chunk with a stone floor in its bottom quarter.
+fn floor_chunk() -> VoxelChunk {
+    let mut chunk = VoxelChunk::new(32);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0; 3],
+            max: [32, 8, 32],
+        },
+        blocks::STONE,
+    );
+    chunk
+}
+
+#[test]
+fn probes_in_solid_blocks_are_left_out() {
+    let probes = bake(&floor_chunk(), 4, &light(Vector3::unit_y()));
+    assert!(probes.is_baked());
+    for x in 0..4 {
+        // probe centres at 4, 12, 20, 28 voxels, only the first row is in the floor
+        assert_eq!(probes.probe(x, 0, 2), [0; 4]);
+        assert_eq!(probes.probe(x, 1, 2)[3], 255);
+    }
+}
+
+#[test]
+fn lit_floors_bounce_light_up() {
+    let chunk = floor_chunk();
+    let [r, g, b, _] = bake(&chunk, 4, &light(Vector3::unit_y())).probe(1, 1, 1);
+    assert!(r > 0 && g > 0 && b > 0, "got {:?}", [r, g, b]);
+
+    // with the sun below the floor its top is in the dark
+    let below = bake(&chunk, 4, &light(-Vector3::unit_y()));
+    assert_eq!(below.probe(1, 1, 1), [0, 0, 0, 255]);
+}
+
+#[test]
+fn sun_comes_through_glass_and_water() {
+    let light = light(Vector3::unit_y());
+    let roofed = |id| {
+        let mut chunk = floor_chunk();
+        chunk.fill_region(
+            VoxelRegion {
+                min: [0, 20, 0],
+                max: [32, 21, 32],
+            },
+            id,
+        );
+        chunk
+    };
+    let open = bake(&floor_chunk(), 4, &light).probe(1, 1, 1);
+    assert_eq!(
+        bake(&roofed(blocks::STONE), 4, &light).probe(1, 1, 1),
+        [0, 0, 0, 255]
+    );
+
+    // glass dims and tints the floor's light, but lets it through
+    let glass = bake(&roofed(blocks::GLASS), 4, &light).probe(1, 1, 1);
+    for i in 0..3 {
+        assert!(
+            glass[i] > 0 && glass[i] < open[i],
+            "got {glass:?}, {open:?}"
+        );
+    }
+
+    // probes under water see the floor through it
+    let mut flooded = floor_chunk();
+    flooded.fill_region(
+        VoxelRegion {
+            min: [0, 8, 0],
+            max: [32, 16, 32],
+        },
+        blocks::WATER,
+    );
+    let [r, g, b, a] = bake(&flooded, 4, &light).probe(1, 1, 1);
+    assert_eq!(a, 255);
+    assert!(b > 0 && b > r, "got {:?}", [r, g, b]);
+}
+
+#[test]
+fn neighbours_shade_probes_near_the_border() {
+    let light = light(Vector3::unit_y());
+    // the floor comes up to 8 voxels under the chunk above, within a shadow ray
+    let mut chunk = VoxelChunk::new(32);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0; 3],
+            max: [32, 24, 32],
+        },
+        blocks::STONE,
+    );
+    let bake_with = |neighbours: &Neighbours| {
+        let mut probes = ProbeGrid::new(4);
+        probes.update_regions(&chunk, neighbours, &[chunk.bounds()], &light);
+        probes.probe(1, 3, 1)
+    };
+
+    let open = bake_with(&Neighbours::default());
+    assert!(open[..3].iter().any(|&v| v > 0), "got {open:?}");
+
+    let mut above = VoxelChunk::new(32);
+    above.fill(blocks::STONE);
+    let mut neighbours = Neighbours::default();
+    neighbours[UP] = Some(Arc::new(above));
+    assert_eq!(bake_with(&neighbours), [0, 0, 0, 255]);
+}
+
+#[test]
+fn edits_are_seen_across_the_sides_they_are_near() {
+    let edit = VoxelRegion {
+        min: [90, 10, 40],
+        max: [92, 12, 42],
+    };
+    // the neighbour along +x sees it on its own -x layer
+    let seen = ProbeGrid::seen_across(100, edit, 3).unwrap();
+    assert_eq!(seen.min, [0, 10, 40]);
+    assert_eq!(seen.max, [1, 12, 42]);
+    // and the one along -x is too far away
+    assert!(ProbeGrid::seen_across(100, edit, 2).is_none());
+    assert_eq!(
+        ProbeGrid::seen_across(100, edit, 0).unwrap().min,
+        [90, 99, 40]
+    );
+}
+
+#[test]
+fn unbaked_grids_cover_nothing() {
+    let probes = ProbeGrid::new(3);
+    assert!(!probes.is_baked());
+    assert_eq!(probes.probe(1, 1, 1), [0; 4]);
+}
+
+/// Updating around an edit has to end up where baking from scratch does.
+#[test]
+fn updates_match_a_full_bake() {
+    let light = light(Vector3::new(0.3, 1.0, 0.2));
+    let mut chunk = VoxelChunk::new(64);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0; 3],
+            max: [64, 10, 64],
+        },
+        blocks::DIRT,
+    );
+    let mut probes = bake(&chunk, 8, &light);
+
+    let edit = VoxelRegion {
+        min: [40, 10, 40],
+        max: [50, 20, 44],
+    };
+    chunk.fill_region(edit, blocks::SAND);
+    let touched = probes.update(&chunk, edit, &light);
+    assert_ne!(
+        touched,
+        probes.bounds(),
+        "edits shouldn't rebake everything"
+    );
+
+    let fresh = bake(&chunk, 8, &light);
+    for z in 0..8 {
+        for y in 0..8 {
+            for x in 0..8 {
+                assert_eq!(
+                    probes.probe(x, y, z),
+                    fresh.probe(x, y, z),
+                    "probe {x} {y} {z}"
+                );
+            }
+        }
+    }
+}
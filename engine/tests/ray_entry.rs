@@ -0,0 +1,186 @@
+//! The CPU port of the shader's chunk entry and march, `raycast::intersect_box` and
+//! `raycast::march_chunk`, checked against rays with known answers.
+
+use cgmath::{InnerSpace, Point3, Vector3};
+use engine::{
+    blocks,
+    raycast::{intersect_box, march_chunk},
+    voxel::{VoxelChunk, VoxelRegion},
+};
+
+const MIN: Point3<f32> = Point3::new(-1.0, -1.0, -1.0);
+const MAX: Point3<f32> = Point3::new(1.0, 1.0, 1.0);
+
+fn assert_span(span: Option<(f32, f32)>, enter: f32, exit: f32) {
+    let (t0, t1) = span.expect("ray should hit the box");
+    assert!(
+        (t0 - enter).abs() < 1e-5 && (t1 - exit).abs() < 1e-5,
+        "got {t0}..{t1}, expected {enter}..{exit}"
+    );
+}
+
+/// Tiny xorshift so the random rays are the same on every run.
+struct Rng(u32);
+
+impl Rng {
+    fn next(&mut self) -> f32 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 17;
+        self.0 ^= self.0 << 5;
+        self.0 as f32 / u32::MAX as f32
+    }
+
+    fn vector(&mut self, scale: f32) -> Vector3<f32> {
+        Vector3::new(self.next(), self.next(), self.next()).map(|v| (v * 2.0 - 1.0) * scale)
+    }
+}
+
+#[test]
+fn enters_from_outside() {
+    let span = intersect_box(Point3::new(0.0, 0.0, -3.0), Vector3::unit_z(), MIN, MAX);
+    assert_span(span, 2.0, 4.0);
+
+    let span = intersect_box(Point3::new(0.5, -4.0, 0.5), Vector3::unit_y(), MIN, MAX);
+    assert_span(span, 3.0, 5.0);
+}
+
+#[test]
+fn starts_at_the_origin_inside() {
+    let span = intersect_box(Point3::new(0.0, 0.5, 0.0), Vector3::unit_y(), MIN, MAX);
+    assert_span(span, 0.0, 0.5);
+
+    let span = intersect_box(Point3::new(0.0, 0.5, 0.0), -Vector3::unit_y(), MIN, MAX);
+    assert_span(span, 0.0, 1.5);
+}
+
+#[test]
+fn misses_are_explicit() {
+    // passes beside the box
+    assert!(intersect_box(Point3::new(0.0, 3.0, -3.0), Vector3::unit_z(), MIN, MAX).is_none());
+    // box is behind the ray
+    assert!(intersect_box(Point3::new(0.0, 0.0, 3.0), Vector3::unit_z(), MIN, MAX).is_none());
+    // diagonal that clears a corner
+    let dir = Vector3::new(1.0, 1.0, 0.0).normalize();
+    assert!(intersect_box(Point3::new(-3.0, -0.5, 0.0), dir, MIN, MAX).is_none());
+}
+
+#[test]
+fn handles_corners_and_edges() {
+    let dir = Vector3::new(1.0, 1.0, 1.0).normalize();
+    let span = intersect_box(Point3::new(-3.0, -3.0, -3.0), dir, MIN, MAX);
+    assert_span(span, 2.0 * 3f32.sqrt(), 4.0 * 3f32.sqrt());
+
+    // starting on the edge at y = z = -1 and heading away only touches the box
+    let dir = Vector3::new(0.0, 1.0, 1.0).normalize();
+    let span = intersect_box(Point3::new(0.0, -1.0, -1.0), -dir, MIN, MAX);
+    assert_span(span, 0.0, 0.0);
+
+    // along the faces, voxels cover `v..v + 1` so only the min side is inside
+    let span = intersect_box(Point3::new(-3.0, -1.0, -1.0), Vector3::unit_x(), MIN, MAX);
+    assert_span(span, 2.0, 4.0);
+    assert!(intersect_box(Point3::new(-3.0, 1.0, 1.0), Vector3::unit_x(), MIN, MAX).is_none());
+}
+
+#[test]
+fn marches_near_to_far() {
+    let mut chunk = VoxelChunk::new(10);
+    chunk.set(5, 5, 2, blocks::STONE);
+    chunk.set(5, 5, 7, blocks::DIRT);
+
+    // from either side the nearer of the two voxels is hit
+    let from_front = march_chunk(&chunk, Point3::new(0.1, 0.1, -3.0), Vector3::unit_z());
+    assert_eq!(from_front, Some([5, 5, 2]));
+    let from_back = march_chunk(&chunk, Point3::new(0.1, 0.1, 3.0), -Vector3::unit_z());
+    assert_eq!(from_back, Some([5, 5, 7]));
+
+    // from in between only what's in front of the camera counts
+    let inside = march_chunk(&chunk, Point3::new(0.1, 0.1, 0.0), Vector3::unit_z());
+    assert_eq!(inside, Some([5, 5, 7]));
+    let inside = march_chunk(&chunk, Point3::new(0.1, 0.1, 0.0), -Vector3::unit_z());
+    assert_eq!(inside, Some([5, 5, 2]));
+
+    assert_eq!(
+        march_chunk(&chunk, Point3::new(0.1, 3.0, -3.0), Vector3::unit_z()),
+        None
+    );
+}
+
+#[test]
+fn hits_the_walls_from_inside() {
+    let mut chunk = VoxelChunk::new(8);
+    chunk.fill(blocks::STONE);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [1; 3],
+            max: [7; 3],
+        },
+        blocks::AIR,
+    );
+
+    let origin = Point3::new(0.1, -0.2, 0.3);
+    for (dir, wall) in [
+        (Vector3::unit_x(), [7, 3, 5]),
+        (-Vector3::unit_x(), [0, 3, 5]),
+        (Vector3::unit_y(), [4, 7, 5]),
+        (-Vector3::unit_y(), [4, 0, 5]),
+        (Vector3::unit_z(), [4, 3, 7]),
+        (-Vector3::unit_z(), [4, 3, 0]),
+    ] {
+        assert_eq!(march_chunk(&chunk, origin, dir), Some(wall), "{dir:?}");
+    }
+}
+
+/// Every hit has to be the nearest solid voxel along the ray, checked by slab
+/// testing all of them. Origins are both inside and outside the chunk.
+#[test]
+fn matches_brute_force() {
+    let size = 16;
+    let mut rng = Rng(0x9e3779b9);
+    let mut chunk = VoxelChunk::new(size);
+    for _ in 0..60 {
+        let [x, y, z] = [(); 3].map(|_| (rng.next() * size as f32) as u32 % size);
+        chunk.set(x, y, z, blocks::STONE);
+    }
+    let solid = chunk
+        .iter()
+        .filter(|(_, id)| *id != blocks::AIR)
+        .map(|(pos, _)| pos)
+        .collect::<Vec<_>>();
+
+    let voxel_box = |[x, y, z]: [u32; 3]| {
+        let min = Point3::new(x, y, z).map(|v| v as f32 / size as f32 * 2.0 - 1.0);
+        (min, min + Vector3::new(1.0, 1.0, 1.0) * 2.0 / size as f32)
+    };
+
+    let mut hits = 0;
+    for i in 0..2000 {
+        let origin = Point3::new(0.0, 0.0, 0.0) + rng.vector(if i % 2 == 0 { 0.9 } else { 3.0 });
+        let dir = rng.vector(1.0).normalize();
+
+        let nearest = solid
+            .iter()
+            .filter_map(|&v| {
+                let (min, max) = voxel_box(v);
+                intersect_box(origin, dir, min, max).map(|(t, _)| t)
+            })
+            .fold(f32::INFINITY, f32::min);
+
+        match march_chunk(&chunk, origin, dir) {
+            Some(voxel) => {
+                let (min, max) = voxel_box(voxel);
+                let (t, _) = intersect_box(origin, dir, min, max)
+                    .unwrap_or_else(|| panic!("ray {origin:?} {dir:?} hit {voxel:?} off its path"));
+                assert!(
+                    t - nearest < 1e-4,
+                    "ray {origin:?} {dir:?} hit {voxel:?} at {t}, nearest is at {nearest}"
+                );
+                hits += 1;
+            }
+            None => assert!(
+                nearest.is_infinite(),
+                "ray {origin:?} {dir:?} missed a voxel at {nearest}"
+            ),
+        }
+    }
+    assert!(hits > 100, "only {hits} rays hit anything");
+}
@@ -0,0 +1,62 @@
+//! Sun shadows across chunk borders, rendered on a software adapter. Fails without
+//! one unless `SKIP_GPU_TESTS=1` is set.
+
+mod common;
+
+use cgmath::Vector3;
+use common::render_target;
+use engine::{
+    blocks, camera::Camera, chunk_gen::FlatGenerator, irradiance::BounceLight, world::VoxelCoord,
+    Scene,
+};
+use std::sync::Arc;
+
+/// Brightness of the pixel in the middle of the image.
+fn centre(image: &image::RgbaImage) -> u32 {
+    let pixel = image.get_pixel(image.width() / 2, image.height() / 2);
+    pixel.0[..3].iter().map(|&v| v as u32).sum()
+}
+
+#[tokio::test]
+async fn walls_in_the_next_chunk_shade_the_ground() {
+    let Some(target) = render_target(32, 32).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+    let mut scene = Scene::new(device, &target.config, Arc::new(FlatGenerator::default()));
+    // low in the east, so a wall there throws a long shadow westwards
+    scene.lighting.sun_direction = Vector3::new(1.0, 0.5, 0.0);
+    scene.world.generate_chunk(device, queue, (0, 0, 0).into());
+    scene.world.generate_chunk(device, queue, (1, 0, 0).into());
+    let light = BounceLight::new(&scene.lighting, &scene.blocks);
+    scene.world.upload_dirty(queue, &light);
+    scene.world.finish_lighting(device, queue);
+
+    // looking down at the ground 15 voxels from the border
+    let mut camera = Camera::default(&target.config);
+    camera.eye = (0.7, 1.5, 0.0).into();
+    camera.target = (0.7, 0.0, 0.01).into();
+    let open = centre(&target.render(&mut scene, &camera));
+
+    // a wall just past the border, in the other chunk
+    let set_wall = |scene: &mut Scene, id| {
+        for x in 100..104 {
+            for y in 50..90 {
+                for z in 0..100 {
+                    scene.world.set_voxel(VoxelCoord::new(x, y, z), id);
+                }
+            }
+        }
+    };
+    set_wall(&mut scene, blocks::STONE);
+    scene.world.upload_dirty(queue, &light);
+    scene.world.finish_lighting(device, queue);
+    let shaded = centre(&target.render(&mut scene, &camera));
+    assert!(shaded < open * 3 / 4, "{shaded} isn't darker than {open}");
+
+    // and the light comes back with the wall gone
+    set_wall(&mut scene, blocks::AIR);
+    scene.world.upload_dirty(queue, &light);
+    scene.world.finish_lighting(device, queue);
+    assert_eq!(centre(&target.render(&mut scene, &camera)), open);
+}
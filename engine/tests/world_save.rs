@@ -0,0 +1,399 @@
+//! Round trips through the save format, first on plain chunk data and then through
+//! `World::save` / `World::load` on a software adapter, which fails without one
+//! unless `SKIP_GPU_TESTS=1` is set.
+
+mod common;
+
+use common::render_target;
+use engine::{
+    blocks,
+    chunk_gen::{CaveGenerator, FileGenerator, FlatGenerator, TerrainGenerator, CHUNK_SIZE},
+    save::{self, SaveHeader},
+    voxel::{VoxelChunk, VoxelRegion},
+    world::{ChunkCoord, VoxelCoord, World},
+};
+use std::{io::ErrorKind, path::PathBuf, sync::Arc};
+
+fn header(chunk_size: u32) -> SaveHeader {
+    versioned_header(save::VERSION, chunk_size)
+}
+
+fn versioned_header(version: u32, chunk_size: u32) -> SaveHeader {
+    SaveHeader {
+        version,
+        seed: 42,
+        generator: "caves".to_string(),
+        chunk_size,
+    }
+}
+
+fn save_path(name: &str) -> PathBuf {
+    let dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("world_save");
+    std::fs::create_dir_all(&dir).unwrap();
+    dir.join(name)
+}
+
+fn encode(header: &SaveHeader, chunks: &[(ChunkCoord, VoxelChunk)]) -> Vec<u8> {
+    let mut bytes = vec![];
+    save::write(&mut bytes, header, chunks.iter().map(|(c, v)| (*c, v))).unwrap();
+    bytes
+}
+
+/// A chunk with a bit of everything: long runs, single voxels and noise.
+fn mixed_chunk(size: u32) -> VoxelChunk {
+    let mut chunk = VoxelChunk::new(size);
+    chunk.fill_region(
+        VoxelRegion {
+            min: [0; 3],
+            max: [size, size / 2, size],
+        },
+        blocks::STONE,
+    );
+    let mut state = 12345u32;
+    for _ in 0..500 {
+        state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+        let [x, y, z] = [0, 8, 16].map(|s| (state >> s) % size);
+        chunk.set(x, y, z, (state >> 24) as u8);
+    }
+    chunk
+}
+
+#[test]
+fn rle_round_trips() {
+    let cases: Vec<Vec<u8>> = vec![
+        vec![],
+        vec![7],
+        vec![0; 200_000],
+        (0..=255).collect(),
+        (0..100_000).map(|i| (i / 3 % 5) as u8).collect(),
+        mixed_chunk(32).to_bytes(),
+    ];
+
+    for data in cases {
+        let encoded = save::encode_rle(&data);
+        assert_eq!(save::decode_rle(&encoded, data.len()).unwrap(), data);
+    }
+}
+
+#[test]
+fn rle_shrinks_uniform_chunks() {
+    let chunk = VoxelChunk::new(CHUNK_SIZE);
+    let encoded = save::encode_rle(&chunk.to_bytes());
+    // runs cap at u16::MAX voxels
+    assert_eq!(
+        encoded.len(),
+        3 * (CHUNK_SIZE.pow(3) as usize).div_ceil(u16::MAX as usize)
+    );
+}
+
+#[test]
+fn rle_rejects_wrong_lengths() {
+    let encoded = save::encode_rle(&[1; 10]);
+    assert!(save::decode_rle(&encoded, 9).is_err());
+    assert!(save::decode_rle(&encoded, 11).is_err());
+    assert!(save::decode_rle(&encoded[..2], 10).is_err());
+}
+
+#[test]
+fn packed_round_trips() {
+    let chunks = [
+        VoxelChunk::new(16),
+        mixed_chunk(16),
+        VoxelChunk::from_raw(4, (0..64).collect()),
+        VoxelChunk::from_raw(3, [blocks::DIRT, blocks::GRASS, blocks::AIR].repeat(9)),
+    ];
+
+    for chunk in chunks {
+        let len = chunk.size().pow(3) as usize;
+        let encoded = save::encode_packed(chunk.packed());
+        let decoded = save::decode_packed(&encoded, len).unwrap();
+        assert_eq!(&decoded, chunk.packed());
+        assert_eq!(decoded.unpack(), chunk.to_bytes());
+    }
+}
+
+#[test]
+fn packed_shrinks_uniform_chunks() {
+    let mut chunk = VoxelChunk::new(CHUNK_SIZE);
+    chunk.fill(blocks::STONE);
+    // bits, palette length and the single palette entry
+    assert_eq!(save::encode_packed(chunk.packed()).len(), 3);
+}
+
+#[test]
+fn packed_rejects_bad_payloads() {
+    let encoded = save::encode_packed(mixed_chunk(8).packed());
+    assert!(save::decode_packed(&encoded, 8 * 8 * 8 + 64).is_err());
+    assert!(save::decode_packed(&encoded[..encoded.len() - 1], 8 * 8 * 8).is_err());
+    assert!(save::decode_packed(&[], 8).is_err());
+    // two bits per voxel can't address a palette of five
+    assert!(save::decode_packed(&[2, 4, 0, 1, 2, 3, 4], 0).is_err());
+}
+
+#[test]
+fn file_round_trips() {
+    for version in 1..=save::VERSION {
+        file_round_trips_as(version);
+    }
+}
+
+fn file_round_trips_as(version: u32) {
+    let header = versioned_header(version, 16);
+    let chunks = vec![
+        (ChunkCoord::new(0, 0, 0), mixed_chunk(16)),
+        (ChunkCoord::new(-3, 7, i32::MIN), VoxelChunk::new(16)),
+        (ChunkCoord::new(1, -1, 2), {
+            let mut chunk = VoxelChunk::new(16);
+            chunk.fill(blocks::SNOW);
+            chunk
+        }),
+    ];
+
+    let file = save::read(encode(&header, &chunks).as_slice()).unwrap();
+    assert_eq!(file.header, header);
+    assert_eq!(file.chunks.len(), chunks.len());
+    for ((coord, voxels), (read_coord, read_voxels)) in chunks.iter().zip(&file.chunks) {
+        assert_eq!(coord, read_coord);
+        assert_eq!(voxels.to_bytes(), read_voxels.to_bytes());
+        assert!(read_voxels.is_modified());
+        assert!(!read_voxels.is_dirty());
+    }
+}
+
+#[test]
+fn empty_file_round_trips() {
+    let header = header(CHUNK_SIZE);
+    let file = save::read(encode(&header, &[]).as_slice()).unwrap();
+    assert_eq!(file.header, header);
+    assert!(file.chunks.is_empty());
+}
+
+#[test]
+fn rejects_bad_files() {
+    let bytes = encode(&header(8), &[(ChunkCoord::new(0, 0, 0), mixed_chunk(8))]);
+
+    let mut bad_magic = bytes.clone();
+    bad_magic[0] = b'X';
+    let err = save::read(bad_magic.as_slice()).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    for version in [0, save::VERSION + 1] {
+        let mut unsupported = bytes.clone();
+        unsupported[4..8].copy_from_slice(&version.to_le_bytes());
+        let err = save::read(unsupported.as_slice()).err().unwrap();
+        assert_eq!(err.kind(), ErrorKind::InvalidData);
+    }
+
+    for len in [0, 3, 10, bytes.len() / 2, bytes.len() - 1] {
+        assert!(save::read(&bytes[..len]).is_err(), "truncated at {len}");
+    }
+}
+
+#[test]
+fn long_generator_ids_round_trip() {
+    let long = SaveHeader {
+        generator: format!("file:/{}", "deep/".repeat(100)),
+        ..header(8)
+    };
+    let file = save::read(encode(&long, &[]).as_slice()).unwrap();
+    assert_eq!(file.header, long);
+
+    // older versions only had a byte for the length
+    let old = SaveHeader { version: 2, ..long };
+    let err = save::write(&mut vec![], &old, std::iter::empty())
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn rejects_chunks_saved_twice() {
+    let coord = ChunkCoord::new(1, 2, 3);
+    let bytes = encode(
+        &header(8),
+        &[(coord, mixed_chunk(8)), (coord, VoxelChunk::new(8))],
+    );
+    let err = save::read(bytes.as_slice()).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+}
+
+#[test]
+fn rejects_huge_chunk_sizes() {
+    let bytes = encode(&header(save::MAX_CHUNK_SIZE + 1), &[]);
+    let err = save::read(bytes.as_slice()).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert!(save::read(encode(&header(save::MAX_CHUNK_SIZE), &[]).as_slice()).is_ok());
+}
+
+#[test]
+fn rejects_oversized_payloads() {
+    // a file with one chunk and nothing but its payload length after that
+    let with_payload_len = |len: u32| {
+        let mut bytes = encode(&header(8), &[]);
+        let count = bytes.len() - 4;
+        bytes[count..].copy_from_slice(&1u32.to_le_bytes());
+        bytes.extend([0; 12]);
+        bytes.extend(len.to_le_bytes());
+        bytes
+    };
+
+    let err = save::read(with_payload_len(u32::MAX).as_slice())
+        .err()
+        .unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+
+    // plausible lengths are read as far as the file goes
+    let max = save::max_payload_len(save::VERSION, 8) as u32;
+    let err = save::read(with_payload_len(max).as_slice()).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::UnexpectedEof);
+}
+
+#[test]
+fn noisy_chunks_fit_the_payload_bound() {
+    // every id once and then noise, so no two words repeat
+    let mut state = 7u32;
+    let data = (0..16u32.pow(3))
+        .map(|i| {
+            state = state.wrapping_mul(1664525).wrapping_add(1013904223);
+            if i < 256 {
+                i as u8
+            } else {
+                (state >> 24) as u8
+            }
+        })
+        .collect();
+    let chunk = VoxelChunk::from_raw(16, data);
+    for version in 1..=save::VERSION {
+        let bytes = encode(
+            &versioned_header(version, 16),
+            &[(ChunkCoord::new(0, 0, 0), chunk.clone())],
+        );
+        let file = save::read(bytes.as_slice()).unwrap();
+        assert_eq!(file.chunks[0].1.to_bytes(), chunk.to_bytes());
+    }
+}
+
+#[test]
+fn write_rejects_mismatched_chunk_sizes() {
+    let mut bytes = vec![];
+    let chunk = VoxelChunk::new(4);
+    let result = save::write(
+        &mut bytes,
+        &header(8),
+        [(ChunkCoord::new(0, 0, 0), &chunk)].into_iter(),
+    );
+    assert!(result.is_err());
+}
+
+#[tokio::test]
+async fn world_round_trips_edits() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+
+    let generator: Arc<dyn TerrainGenerator> = Arc::new(CaveGenerator::new(9));
+    let mut world = World::new(device, generator.clone());
+    let edited = ChunkCoord::new(0, 0, 0);
+    let untouched = ChunkCoord::new(1, 0, 0);
+    world.generate_chunk(device, queue, edited);
+    world.generate_chunk(device, queue, untouched);
+
+    let voxel = VoxelCoord::new(10, 20, 30);
+    world.set_voxel(voxel, blocks::SAND);
+    // an edited chunk that got unloaded still has to end up in the save
+    let evicted = ChunkCoord::new(0, 2, 0);
+    world.generate_chunk(device, queue, evicted);
+    world.set_voxel(VoxelCoord::new(5, 205, 5), blocks::SNOW);
+    world.remove_chunk(device, evicted);
+    assert!(world.is_stored(evicted));
+
+    let path = save_path("round_trip.sav");
+    // the second save replaces the first
+    world.save(&path).unwrap();
+    world.save(&path).unwrap();
+    assert!(!save_path("round_trip.sav.tmp").exists());
+
+    let mut loaded = World::new(device, Arc::new(FlatGenerator::default()));
+    loaded.generate_chunk(device, queue, edited);
+    loaded.load(&path).unwrap();
+
+    assert_eq!(loaded.generator().id(), "caves");
+    assert_eq!(loaded.generator().seed(), 9);
+    assert!(loaded.is_empty(), "load should unload every chunk");
+    assert!(loaded.is_stored(edited));
+    assert!(loaded.is_stored(evicted));
+    assert!(
+        !loaded.is_stored(untouched),
+        "untouched chunks are regenerated"
+    );
+
+    assert!(loaded.restore_chunk(device, queue, edited));
+    assert!(loaded.restore_chunk(device, queue, evicted));
+    loaded.generate_chunk(device, queue, untouched);
+    assert_eq!(loaded.get_voxel(voxel), Some(blocks::SAND));
+    assert_eq!(
+        loaded.get_voxel(VoxelCoord::new(5, 205, 5)),
+        Some(blocks::SNOW)
+    );
+    assert_eq!(
+        loaded.get_chunk(edited).unwrap().voxels.to_bytes(),
+        world.get_chunk(edited).unwrap().voxels.to_bytes()
+    );
+    assert_eq!(
+        loaded.get_chunk(untouched).unwrap().voxels.to_bytes(),
+        generator.generate(untouched).to_bytes()
+    );
+}
+
+#[tokio::test]
+async fn file_generators_round_trip() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+    let (device, queue) = (&target.device, &target.queue);
+
+    let dir = save_path("file_generator_chunks");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("0_0_0.chunk"),
+        vec![blocks::DIRT; CHUNK_SIZE.pow(3) as usize],
+    )
+    .unwrap();
+    let generator = FileGenerator::new(&dir).unwrap();
+
+    let mut world = World::new(device, Arc::new(generator));
+    world.generate_chunk(device, queue, ChunkCoord::new(0, 0, 0));
+    world.set_voxel(VoxelCoord::new(1, 2, 3), blocks::SAND);
+    let path = save_path("file_generator.sav");
+    world.save(&path).unwrap();
+
+    // loading from any other generator has to bring the file one back
+    let mut loaded = World::new(device, Arc::new(FlatGenerator::default()));
+    loaded.load(&path).unwrap();
+    assert_eq!(loaded.generator().id(), world.generator().id());
+    assert!(loaded.restore_chunk(device, queue, ChunkCoord::new(0, 0, 0)));
+    assert_eq!(
+        loaded.get_voxel(VoxelCoord::new(1, 2, 3)),
+        Some(blocks::SAND)
+    );
+    assert_eq!(
+        loaded.get_voxel(VoxelCoord::new(4, 5, 6)),
+        Some(blocks::DIRT)
+    );
+}
+
+#[tokio::test]
+async fn load_refuses_other_chunk_sizes() {
+    let Some(target) = render_target(1, 1).await else {
+        return;
+    };
+
+    let path = save_path("small_chunks.sav");
+    std::fs::write(&path, encode(&header(8), &[])).unwrap();
+
+    let mut world = World::new(&target.device, Arc::new(FlatGenerator::default()));
+    let err = world.load(&path).err().unwrap();
+    assert_eq!(err.kind(), ErrorKind::InvalidData);
+    assert_eq!(world.generator().id(), "flat");
+}
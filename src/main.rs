@@ -1,8 +1,14 @@
 #![windows_subsystem = "windows"]
-use engine::{display_handler::GameWindow, *};
+use engine::{chunk_gen, display_handler::GameWindow, *};
 
+/// Usage: `voxel_game [caves|heightmap|flat|file:<dir>] [seed]`
 #[tokio::main]
 async fn main() {
+    let mut args = std::env::args().skip(1);
+    let name = args.next().unwrap_or("caves".to_string());
+    let seed = args.next().and_then(|s| s.parse().ok()).unwrap_or(5);
+    let generator = chunk_gen::from_name(&name, seed).expect("Can't set up the terrain generator");
+
     let window = GameWindow::new().await;
-    run(window).await;
+    run(window, generator).await;
 }